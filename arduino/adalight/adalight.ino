@@ -0,0 +1,138 @@
+// ========= PREPROCESSOR CONFIGURATION =========
+
+#define SERIAL_BAUD 500000
+#define MAX_BRIGHTNESS 240 // keep this at the maximum value your PSU can handle and do the rest in the shader
+
+#define STRIP1_LENGTH 88
+#define STRIP1_DATA 2
+
+#define STRIP2_LENGTH 91
+#define STRIP2_DATA 4
+
+// (do not change anything below this line)
+#define SERIAL_TIMEOUT 5000
+
+#ifdef STRIP1_LENGTH
+    #ifdef STRIP2_LENGTH
+        #ifdef STRIP3_LENGTH
+            #ifdef STRIP4_LENGTH
+                #define BUFFER_LENGTH (STRIP1_LENGTH + STRIP2_LENGTH + STRIP3_LENGTH + STRIP4_LENGTH)
+                #define STRIP_COUNT 4
+            #else
+                #define BUFFER_LENGTH (STRIP1_LENGTH + STRIP2_LENGTH + STRIP3_LENGTH)
+                #define STRIP_COUNT 3
+            #endif
+        #else
+            #define BUFFER_LENGTH (STRIP1_LENGTH + STRIP2_LENGTH)
+            #define STRIP_COUNT 2
+        #endif
+    #else
+        #define BUFFER_LENGTH STRIP1_LENGTH
+        #define STRIP_COUNT 1
+    #endif
+#else
+    #error "At least one and at most four strips must be defined"
+#endif
+
+#define FASTLED_ALLOW_INTERRUPTS 0
+#include <FastLED.h>
+
+// ========== PREPROCESSOR CONFIGURATION END ==========
+
+// this is a variant of arduino.ino that expects every frame to be prefixed with an adalight header:
+// the magic word "Ada", the high and low byte of the led count minus one and a checksum of both bytes xor 0x55.
+// if a byte gets lost, the frame is discarded and the receiver resynchronises on the next header.
+
+#define HEADER_OK 0
+#define HEADER_TIMEOUT 1
+#define HEADER_INVALID 2
+
+const uint8_t MAGIC[] = { 'A', 'd', 'a' }; //!< magic word at the start of every frame
+
+struct CRGB leds[BUFFER_LENGTH]; //!< buffer for all leds
+
+int timeout_steps = 0; //!< timeout animation counter
+
+void setup() {
+    // init serial
+    Serial.begin(SERIAL_BAUD);
+    Serial.setTimeout(SERIAL_TIMEOUT);
+
+    // init leds
+    #if STRIP_COUNT >= 1
+    FastLED.addLeds<WS2812B, STRIP1_DATA, GRB>(leds, STRIP1_LENGTH);
+    #endif
+
+    #if STRIP_COUNT >= 2
+    FastLED.addLeds<WS2812B, STRIP2_DATA, GRB>(leds + STRIP1_LENGTH, STRIP2_LENGTH);
+    #endif
+
+    #if STRIP_COUNT >= 3
+    FastLED.addLeds<WS2812B, STRIP3_DATA, GRB>(leds + STRIP1_LENGTH + STRIP2_LENGTH, STRIP3_LENGTH);
+    #endif
+
+    #if STRIP_COUNT >= 4
+    FastLED.addLeds<WS2812B, STRIP4_DATA, GRB>(leds + STRIP1_LENGTH + STRIP2_LENGTH + STRIP3_LENGTH, STRIP4_LENGTH);
+    #endif
+
+    // configure fastled
+    FastLED.setDither(0);
+    FastLED.setBrightness(MAX_BRIGHTNESS);
+    FastLED.setMaxRefreshRate(0);
+}
+
+int read_header() {
+    // scan for the magic word, skipping any garbage in front of it
+    uint8_t matched = 0;
+    uint8_t byte;
+    while (matched < sizeof(MAGIC)) {
+        if (!Serial.readBytes((char*) &byte, 1))
+            return HEADER_TIMEOUT;
+
+        if (byte == MAGIC[matched])
+            matched++;
+        else
+            matched = byte == MAGIC[0] ? 1 : 0;
+    }
+
+    // read the led count and checksum
+    uint8_t header[3];
+    if (Serial.readBytes((char*) header, 3) != 3)
+        return HEADER_TIMEOUT;
+
+    // verify the checksum and led count
+    if ((header[0] ^ header[1] ^ 0x55) != header[2])
+        return HEADER_INVALID;
+    if (((header[0] << 8) | header[1]) + 1 != BUFFER_LENGTH)
+        return HEADER_INVALID;
+
+    return HEADER_OK;
+}
+
+void loop() {
+    // try to read the header and leds
+    int status = read_header();
+    if (status == HEADER_INVALID)
+        return; // discard the frame and resynchronise on the next header
+
+    int i = status == HEADER_OK ? Serial.readBytes((char*) leds, BUFFER_LENGTH * 3) : 0;
+    // check if data was fully read
+    if (i != BUFFER_LENGTH * 3) {
+        // if not, start timeout animation
+        if (!timeout_steps) {
+            Serial.setTimeout(30);
+            FastLED.setCorrection(CRGB(255, 0, 0));
+        }
+
+        // timeout animation
+        timeout_steps++;
+        memset(leds, (sin(timeout_steps * 0.025) + 1) / 2 * 220 + 20, BUFFER_LENGTH * 3);
+    } else if (timeout_steps) {
+        // otherwise reset timeout counter
+        Serial.setTimeout(SERIAL_TIMEOUT);
+        FastLED.setCorrection(CRGB(255, 255, 255));
+        timeout_steps = 0;
+    }
+
+    FastLED.show();
+}
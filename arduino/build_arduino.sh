@@ -9,7 +9,7 @@
 
 # check if the first argument is empty
 if [ -z "$1" ]; then
-    echo "./build_arduino.sh <port>"
+    echo "./build_arduino.sh <port> [sketch]"
     exit 1
 fi
 
@@ -21,4 +21,5 @@ fi
 
 # build and upload the sketch
 arduino-cli lib install FastLED
-arduino-cli compile -b arduino:avr:leonardo -p "$1" -u --warnings all arduino*
+# (pass "adalight" as the second argument to upload the adalight variant instead)
+arduino-cli compile -b arduino:avr:leonardo -p "$1" -u --warnings all "${2:-arduino.ino}"
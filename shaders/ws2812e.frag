@@ -9,7 +9,8 @@
 in vec2 TexCoord;
 out vec4 FragColor;
 
-uniform sampler2D texture1;
+uniform sampler2D texture0;
+uniform vec4 texture0Rect; // region of the texture holding the capture session
 
 vec3 norm(vec3 color) {
 	float max_component = max(max(color.r, color.g), color.b);
@@ -20,7 +21,7 @@ vec3 norm(vec3 color) {
 }
 
 void main() {
-	vec3 color = texture(texture1, TexCoord).rgb;
+	vec3 color = texture(texture0, texture0Rect.xy + TexCoord * texture0Rect.zw).rgb;
 	color.g *= 0.69; // adjust green and blue to closer match sRGB
 	color.b *= 0.31;
 	vec3 norm_color = norm(color);
@@ -1,7 +1,10 @@
-use std::{fs::File, io::BufReader, path::PathBuf};
+use std::{fmt::{self, Display, Formatter}, fs::File, io::BufReader, path::PathBuf};
 
 use anyhow::Context;
-use serde::Deserialize;
+use serde::{de::Error, Deserialize, Deserializer};
+use serde_yml::Value;
+
+pub mod validation;
 
 ///
 /// The configuration of the application
@@ -39,6 +42,17 @@ impl Configuration {
         Ok(config)
     }
 
+    ///
+    /// Validates the configuration, checking ids, references and mappings
+    ///
+    /// # Returns
+    ///
+    /// Every problem found in the configuration, empty if the configuration is valid
+    ///
+    pub fn validate(&self) -> Vec<validation::Problem> {
+        validation::validate(self)
+    }
+
 }
 
 // ====== Connector ======
@@ -51,7 +65,33 @@ pub struct Connector {
     /// List of physical devices
     pub devices: Vec<Device>,
     /// List of virtual strips
-    pub strips: Vec<Strip>
+    pub strips: Vec<Strip>,
+    /// Backoff for reconnecting devices that failed
+    #[serde(default)]
+    pub reconnect: Reconnect,
+    /// The longest time in milliseconds a device goes without a frame while its strips are unchanged
+    #[serde(default = "default_keepalive")]
+    pub keepalive: u64
+}
+
+///
+/// The reconnect backoff of failed devices
+///
+/// A device that fails is reconnected after the initial delay, which doubles with every failed attempt up to the maximum delay.
+/// All other devices keep receiving frames meanwhile.
+///
+#[derive(Deserialize)]
+pub struct Reconnect {
+    /// The delay before the first reconnect attempt in milliseconds
+    pub initial_delay: u64,
+    /// The maximum delay between reconnect attempts in milliseconds
+    pub max_delay: u64
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self { initial_delay: 1000, max_delay: 30000 }
+    }
 }
 
 ///
@@ -59,24 +99,121 @@ pub struct Connector {
 ///
 /// A physical device is an arduino or similar microcontroller that controls a set of physical led strips
 ///
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct Device {
     /// The unique identifier of the device
     pub id: u64,
-    /// The port the device is connected to, e.g. COM3 or /dev/ttyACM0
-    pub port: String,
-    /// The baud rate of the serial connection (ensure that the device is configured to use the same baud rate)
-    pub baud_rate: u32,
+    /// The sink the device is driven through, selected by the `type` key (defaults to serial if omitted)
+    #[serde(flatten, deserialize_with = "Sink::deserialize_with_default")]
+    pub sink: Sink,
     /// The list of physical strips connected to the device
     pub physical_strips: Vec<PhysicalStrip>
 }
 
+///
+/// The configuration of a device sink
+///
+/// The sink specifies how the buffer of a device is transferred to the hardware. It is selected by the `type` key of the device.
+///
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Sink {
+    /// Arduino or similar microcontroller connected via a serial port
+    Serial {
+        /// The port the device is connected to, e.g. COM3 or /dev/ttyACM0
+        port: String,
+        /// The baud rate of the serial connection (ensure that the device is configured to use the same baud rate)
+        baud_rate: u32,
+        /// The framing of the serial data (ensure that the device runs the matching firmware)
+        #[serde(default)]
+        protocol: SerialProtocol
+    },
+    /// WLED controller on the network driven by the realtime UDP protocol
+    Wled {
+        /// The hostname or ip address of the controller
+        address: String,
+        /// The realtime UDP port of the controller
+        #[serde(default = "default_wled_port")]
+        port: u16,
+        /// The seconds the controller waits after the last packet before returning to its normal mode (255 waits forever)
+        #[serde(default = "default_wled_timeout")]
+        timeout: u8
+    },
+    /// E1.31 (sACN) receiver, buffers larger than 170 leds are split across consecutive universes
+    Sacn {
+        /// The unicast address of the receiver, if omitted each universe is sent to its multicast group
+        address: Option<String>,
+        /// The first universe
+        #[serde(default = "default_sacn_universe")]
+        universe: u16,
+        /// The priority of the source (0-200)
+        #[serde(default = "default_sacn_priority")]
+        priority: u8,
+        /// The name of the source shown by the receiver
+        #[serde(default = "default_sacn_source_name")]
+        source_name: String
+    },
+    /// Art-Net receiver, buffers larger than 170 leds are split across consecutive universes
+    Artnet {
+        /// The unicast or broadcast address of the receiver
+        address: String,
+        /// The first universe (15 bit port-address)
+        #[serde(default)]
+        universe: u16
+    }
+}
+
+///
+/// The framing of the data sent to a serial device
+///
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SerialProtocol {
+    /// Raw rgb bytes, used by arduino.ino
+    #[default]
+    Raw,
+    /// Adalight header with led count and checksum in front of every frame, used by adalight.ino and most adalight firmwares
+    Adalight
+}
+
+fn default_keepalive() -> u64 { 1000 }
+fn default_idle_fade() -> u64 { 1000 }
+fn default_wled_port() -> u16 { 21324 }
+fn default_wled_timeout() -> u8 { 2 }
+fn default_sacn_universe() -> u16 { 1 }
+fn default_sacn_priority() -> u8 { 100 }
+fn default_sacn_source_name() -> String { "ambient-led".to_string() }
+
+impl Display for Sink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Sink::Serial { port, baud_rate, .. } => write!(f, "{} @{}Bd", port, baud_rate),
+            Sink::Wled { address, port, .. } => write!(f, "wled {}:{}", address, port),
+            Sink::Sacn { address, universe, .. } => write!(f, "sacn {} from universe {}", address.as_deref().unwrap_or("multicast"), universe),
+            Sink::Artnet { address, universe } => write!(f, "art-net {} from universe {}", address, universe)
+        }
+    }
+}
+
+impl Sink {
+
+    ///
+    /// Deserializes the sink, falling back to a serial sink if no type is specified
+    ///
+    fn deserialize_with_default<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut mapping = serde_yml::Mapping::deserialize(deserializer)?;
+        mapping.entry(Value::from("type")).or_insert(Value::from("serial"));
+        Sink::deserialize(Value::Mapping(mapping)).map_err(D::Error::custom)
+    }
+
+}
+
 ///
 /// The configuration of a physical led strip
 ///
 /// A physical led strip is a strip of leds connected to a physical device. Any number of virtual strips can be mapped this physical strip.
 ///
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct PhysicalStrip {
     /// The amount of leds on the strip, this is required to calculate the buffer size of the serial connection
     pub leds: u16
@@ -87,7 +224,7 @@ pub struct PhysicalStrip {
 ///
 /// A virtual led strip is a strip of leds that is mapped to one or multiple physical led strips. The mapping specifies the offset and length of the physical strips and is applied in the order of the mappings.
 ///
-#[derive(Deserialize)]
+#[derive(Deserialize, PartialEq)]
 pub struct Strip {
     /// The unique identifier of the strip
     pub id: u64,
@@ -102,7 +239,7 @@ pub struct Strip {
 ///
 /// The mapping specifies the offset and length of a physical strip on a specific device.
 ///
-#[derive(Deserialize)]
+#[derive(Deserialize, PartialEq)]
 pub struct Mapping {
     /// The unique identifier of the device the physical strip is connected to
     pub device_id: u64,
@@ -111,7 +248,31 @@ pub struct Mapping {
     /// The offset of the physical strip on the virtual strip
     pub offset: u16,
     /// The length of the physical strip on the virtual strip
-    pub length: u16
+    pub length: u16,
+    /// Whether the segment runs in the opposite direction on the physical strip
+    #[serde(default)]
+    pub reverse: bool,
+    /// Additional physical strips the same segment is copied to
+    #[serde(default)]
+    pub mirrors: Vec<Mirror>
+}
+
+///
+/// An additional target of a mapping
+///
+/// A mirror receives the same segment of the virtual strip as the mapping it belongs to, the length is taken from the mapping.
+///
+#[derive(Deserialize, PartialEq)]
+pub struct Mirror {
+    /// The unique identifier of the device the physical strip is connected to
+    pub device_id: u64,
+    /// The unique identifier of the physical strip on the device
+    pub physical_strip_idx: u8,
+    /// The offset of the segment on the physical strip
+    pub offset: u16,
+    /// Whether the segment runs in the opposite direction on the physical strip
+    #[serde(default)]
+    pub reverse: bool
 }
 
 // ====== Screencopy ======
@@ -121,42 +282,336 @@ pub struct Mapping {
 ///
 #[derive(Deserialize)]
 pub struct Screencopy {
-    /// The path to the gbm device that is used for rendering
-    pub gbm_device: String,
+    /// The path to the gbm device that is used for rendering, picked from the gpu of the compositor if omitted
+    #[serde(default)]
+    pub gbm_device: Option<String>,
     /// The list of capture sessions
-    pub capture_sessions: Vec<CaptureSession>
+    pub capture_sessions: Vec<CaptureSession>,
+    /// The color strips are filled with while the output of one of their capture sessions is unplugged
+    #[serde(default)]
+    pub fallback_color: [u8; 3],
+    /// Whether capture sessions on the same output are cut from a single capture of the entire output
+    #[serde(default)]
+    pub share_captures: bool,
+    /// What happens to the strips while the user is idle, capturing continues if omitted
+    pub idle: Option<Idle>
+}
+
+///
+/// The behavior while the user is idle
+///
+/// Once the user is idle, capturing stops and the strips fade to the idle color. They fade back in on activity.
+///
+#[derive(Deserialize, PartialEq)]
+pub struct Idle {
+    /// The seconds without user activity after which the user is idle
+    pub timeout: u64,
+    /// The color the strips fade to (r, g, b)
+    #[serde(default)]
+    pub color: [u8; 3],
+    /// The duration of the fade in milliseconds
+    #[serde(default = "default_idle_fade")]
+    pub fade: u64
 }
 
 ///
 /// The configuration of a capture session
 ///
-/// A capture session specifies the region of an output that is captured and imported into the render pipeline as a texture
+/// A capture session specifies the region of an output that is captured and imported into the render pipeline as a texture.
+/// Without an output, the region is given in the global layout of the compositor and composed of the captures of every output it overlaps.
 ///
-#[derive(Deserialize)]
+#[derive(Deserialize, PartialEq)]
 pub struct CaptureSession {
     /// The unique identifier of the capture session
     pub id: u64,
-    /// The output that is captured (this will first attempt to find a matching output by name, if no output is found it will attempt to find a matching output by description if it is provided)
-    pub output: String,
-    /// The region of the output that is captured
+    /// The output that is captured, either a single selector or a list of selectors that are tried in order, the global layout if omitted
+    #[serde(default)]
+    pub output: Option<Output>,
+    /// The region of the output, or of the bounding box of the global layout, that is captured
     pub region: Region
 }
 
+///
+/// One or more output selectors, the first selector matching any output wins
+///
+#[derive(Deserialize, PartialEq)]
+#[serde(from = "OneOrMany<OutputSelector>")]
+pub struct Output(pub Vec<OutputSelector>);
+
+///
+/// A selector matching outputs by their properties
+///
+/// Every property is a pattern that may contain `*` (any text) and `?` (any character) wildcards.
+/// All specified properties have to match. A plain string is a selector for the name of the output, which falls back
+/// to a part of the description like output names did before selectors existed.
+///
+#[derive(Deserialize, PartialEq, Default)]
+#[serde(from = "OutputSelectorRepr")]
+pub struct OutputSelector {
+    /// The connector name of the output, e.g. DP-3
+    pub name: Option<String>,
+    /// The description of the output as reported by the compositor
+    pub description: Option<String>,
+    /// The manufacturer of the monitor
+    pub make: Option<String>,
+    /// The model of the monitor
+    pub model: Option<String>,
+    /// The serial number of the monitor, parsed out of the description as the text following the make and model
+    pub serial: Option<String>,
+    /// Whether the selector was written as a plain string
+    pub plain: bool
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>)
+}
+
+impl From<OneOrMany<OutputSelector>> for Output {
+    fn from(value: OneOrMany<OutputSelector>) -> Self {
+        match value {
+            OneOrMany::One(selector) => Output(vec![selector]),
+            OneOrMany::Many(selectors) => Output(selectors)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OutputSelectorRepr {
+    Name(String),
+    Properties(OutputProperties)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OutputProperties {
+    name: Option<String>,
+    description: Option<String>,
+    make: Option<String>,
+    model: Option<String>,
+    serial: Option<String>
+}
+
+impl From<OutputSelectorRepr> for OutputSelector {
+    fn from(value: OutputSelectorRepr) -> Self {
+        match value {
+            OutputSelectorRepr::Name(name) => OutputSelector { name: Some(name), plain: true, ..Default::default() },
+            OutputSelectorRepr::Properties(OutputProperties { name, description, make, model, serial }) =>
+                OutputSelector { name, description, make, model, serial, plain: false }
+        }
+    }
+}
+
+impl OutputSelector {
+
+    ///
+    /// Get the specified properties of the selector as pairs of property name and pattern
+    ///
+    pub fn properties(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [("name", &self.name), ("description", &self.description), ("make", &self.make), ("model", &self.model), ("serial", &self.serial)]
+            .into_iter()
+            .filter_map(|(property, pattern)| pattern.as_deref().map(|pattern| (property, pattern)))
+    }
+
+}
+
+impl Display for Output {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let selectors = self.0.iter().map(|selector| match selector {
+            OutputSelector { name: Some(name), description: None, make: None, model: None, serial: None, .. } => name.clone(),
+            selector => selector.properties().map(|(property, pattern)| format!("{}={}", property, pattern)).collect::<Vec<_>>().join(", ")
+        });
+        write!(f, "{}", selectors.collect::<Vec<_>>().join(" | "))
+    }
+}
+
 ///
 /// The region of an output that is captured.
 ///
 /// Please keep in mind that these are local to the output and also virtual coordinates, so scaling applies.
+/// Every length can also be a percentage of the logical size of the output, which is re-resolved whenever the output changes.
+/// Regions in the global layout are relative to the top-left corner of the bounding box of all outputs and resolved against its size.
+///
+#[derive(Deserialize, PartialEq)]
+#[serde(try_from = "RegionRepr")]
+pub enum Region {
+    /// A strip along an edge of the output spanning the entire edge
+    Edge {
+        /// The edge of the output
+        edge: Edge,
+        /// The depth of the strip, perpendicular to the edge
+        depth: Length
+    },
+    /// A rectangle within the output
+    Rectangle {
+        /// The left coordinate of the region
+        left: Length,
+        /// The top coordinate of the region
+        top: Length,
+        /// The width of the region
+        width: Length,
+        /// The height of the region
+        height: Length
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegionRepr {
+    edge: Option<Edge>,
+    depth: Option<Length>,
+    left: Option<Length>,
+    top: Option<Length>,
+    width: Option<Length>,
+    height: Option<Length>
+}
+
+impl TryFrom<RegionRepr> for Region {
+    type Error = String;
+
+    fn try_from(value: RegionRepr) -> Result<Self, Self::Error> {
+        let RegionRepr { edge, depth, left, top, width, height } = value;
+        let rectangle = [("left", left.is_some()), ("top", top.is_some()), ("width", width.is_some()), ("height", height.is_some())];
+
+        // the form of the region is told apart by the keys that are present
+        if edge.is_some() || depth.is_some() {
+            if let Some((key, _)) = rectangle.iter().find(|(_, present)| *present) {
+                return Err(format!("region mixes `{}` with `edge` and `depth`, specify either an edge or a rectangle", key));
+            }
+            return match (edge, depth) {
+                (Some(edge), Some(depth)) => Ok(Region::Edge { edge, depth }),
+                (None, _) => Err("edge region is missing `edge`".to_string()),
+                (_, None) => Err("edge region is missing `depth`".to_string())
+            };
+        }
+
+        match (left, top, width, height) {
+            (Some(left), Some(top), Some(width), Some(height)) => Ok(Region::Rectangle { left, top, width, height }),
+            _ if rectangle.iter().all(|(_, present)| !present) => Err("region needs either `edge` and `depth`, or `left`, `top`, `width` and `height`".to_string()),
+            _ => {
+                let missing = rectangle.iter().filter(|(_, present)| !present).map(|(key, _)| format!("`{}`", key)).collect::<Vec<_>>();
+                Err(format!("rectangle region is missing {}", missing.join(", ")))
+            }
+        }
+    }
+}
+
+///
+/// An edge of an output
+///
+#[derive(Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right
+}
+
+///
+/// A length in logical coordinates or relative to the size of the output
 ///
+#[derive(Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(try_from = "LengthRepr")]
+pub enum Length {
+    /// A length in logical coordinates
+    Absolute(i32),
+    /// A percentage of the logical size of the output, written as e.g. `10%`
+    Relative(f32)
+}
+
 #[derive(Deserialize)]
-pub struct Region {
-    /// The left coordinate of the region
-    pub left: i32,
-    /// The top coordinate of the region
-    pub top: i32,
-    /// The width of the region
-    pub width: i32,
-    /// The height of the region
-    pub height: i32
+#[serde(untagged)]
+enum LengthRepr {
+    Absolute(i32),
+    Relative(String)
+}
+
+impl TryFrom<LengthRepr> for Length {
+    type Error = String;
+
+    fn try_from(value: LengthRepr) -> Result<Self, Self::Error> {
+        match value {
+            LengthRepr::Absolute(length) => Ok(Length::Absolute(length)),
+            LengthRepr::Relative(length) => length.trim().strip_suffix('%')
+                .and_then(|percent| percent.trim().parse::<f32>().ok())
+                .map(Length::Relative)
+                .ok_or_else(|| format!("invalid length '{}', expected a number or a percentage like 10%", length))
+        }
+    }
+}
+
+impl Length {
+
+    ///
+    /// Resolve the length against the size of the output along the same axis
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The logical size of the output along the axis
+    ///
+    pub fn resolve(&self, size: i32) -> i32 {
+        match self {
+            Length::Absolute(length) => *length,
+            Length::Relative(percent) => (size as f32 * percent / 100.0).round() as i32
+        }
+    }
+
+}
+
+impl Region {
+
+    ///
+    /// Resolve the region to logical coordinates as left, top, width and height
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The logical width of the output
+    /// * `height` - The logical height of the output
+    ///
+    pub fn resolve(&self, width: i32, height: i32) -> (i32, i32, i32, i32) {
+        match self {
+            Region::Edge { edge: Edge::Top, depth } => (0, 0, width, depth.resolve(height)),
+            Region::Edge { edge: Edge::Bottom, depth } => (0, height - depth.resolve(height), width, depth.resolve(height)),
+            Region::Edge { edge: Edge::Left, depth } => (0, 0, depth.resolve(width), height),
+            Region::Edge { edge: Edge::Right, depth } => (width - depth.resolve(width), 0, depth.resolve(width), height),
+            Region::Rectangle { left, top, width: region_width, height: region_height } =>
+                (left.resolve(width), top.resolve(height), region_width.resolve(width), region_height.resolve(height))
+        }
+    }
+
+    ///
+    /// Check whether the region depends on the size of the output
+    ///
+    pub fn is_relative(&self) -> bool {
+        match self {
+            Region::Edge { .. } => true,
+            Region::Rectangle { left, top, width, height } =>
+                [left, top, width, height].iter().any(|length| matches!(length, Length::Relative(_)))
+        }
+    }
+
+}
+
+impl Display for Length {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Length::Absolute(length) => write!(f, "{}", length),
+            Length::Relative(percent) => write!(f, "{}%", percent)
+        }
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Edge { edge, depth } => write!(f, "{:?} edge with depth {}", edge, depth),
+            Region::Rectangle { left, top, width, height } => write!(f, "{}, {} with size {}x{}", left, top, width, height)
+        }
+    }
 }
 
 // ====== Render Pipeline ======
@@ -175,7 +630,7 @@ pub struct RenderPipeline {
 ///
 /// A program specifies the shaders that are used to render a specific strip. You can specify multiple capture sessions that are used as textures in the shaders.
 ///
-#[derive(Deserialize)]
+#[derive(Deserialize, PartialEq)]
 pub struct Program {
     /// The unique identifier of the program
     pub id: u64,
@@ -188,3 +643,59 @@ pub struct Program {
     /// The unique identifier of the strip that is rendered by this program
     pub strip_id: u64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Edge, Length, Region};
+
+    fn length(yaml: &str) -> Result<Length, serde_yml::Error> {
+        serde_yml::from_str(yaml)
+    }
+
+    #[test]
+    fn parse_length() {
+        assert_eq!(length("42").unwrap(), Length::Absolute(42));
+        assert_eq!(length("10%").unwrap(), Length::Relative(10.0));
+        assert_eq!(length("' 12.5 % '").unwrap(), Length::Relative(12.5));
+        assert!(length("10px").is_err());
+        assert!(length("%").is_err());
+    }
+
+    #[test]
+    fn resolve_length() {
+        assert_eq!(Length::Absolute(30).resolve(1920), 30);
+        assert_eq!(Length::Relative(10.0).resolve(1920), 192);
+        assert_eq!(Length::Relative(33.3).resolve(1000), 333);
+    }
+
+    #[test]
+    fn resolve_edges() {
+        let depth = Length::Relative(10.0);
+        assert_eq!(Region::Edge { edge: Edge::Top, depth }.resolve(1920, 1080), (0, 0, 1920, 108));
+        assert_eq!(Region::Edge { edge: Edge::Bottom, depth }.resolve(1920, 1080), (0, 972, 1920, 108));
+        assert_eq!(Region::Edge { edge: Edge::Left, depth }.resolve(1920, 1080), (0, 0, 192, 1080));
+        assert_eq!(Region::Edge { edge: Edge::Right, depth }.resolve(1920, 1080), (1728, 0, 192, 1080));
+    }
+
+    #[test]
+    fn region_errors_name_the_field() {
+        let error = |yaml: &str| serde_yml::from_str::<Region>(yaml).err().unwrap().to_string();
+        assert!(error("{ egde: top, depth: 10% }").contains("unknown field `egde`"));
+        assert!(error("{ edge: top }").contains("missing `depth`"));
+        assert!(error("{ edge: top, depth: 10%, width: 20 }").contains("mixes `width`"));
+        assert!(error("{ left: 0, top: 0, widht: 20, height: 20 }").contains("unknown field `widht`"));
+        assert!(error("{ left: 0, top: 0, height: 20 }").contains("missing `width`"));
+        assert!(error("{}").contains("needs either"));
+    }
+
+    #[test]
+    fn resolve_rectangle() {
+        let region: Region = serde_yml::from_str("{ left: 10, top: 90%, width: 50%, height: 20 }").unwrap();
+        assert_eq!(region.resolve(1920, 1080), (10, 972, 960, 20));
+        assert!(region.is_relative());
+
+        let region: Region = serde_yml::from_str("{ left: 0, top: 0, width: 100, height: 20 }").unwrap();
+        assert_eq!(region.resolve(1920, 1080), (0, 0, 100, 20));
+        assert!(!region.is_relative());
+    }
+}
@@ -0,0 +1,480 @@
+use std::{collections::{HashMap, HashSet}, fmt::{self, Display, Formatter}, ops::Range};
+
+use super::{Configuration, Length, Region, SerialProtocol, Sink};
+use crate::connector::e131::LEDS_PER_UNIVERSE;
+
+/// Milliseconds the arduino firmware waits for a frame before showing its timeout animation
+const SERIAL_TIMEOUT: u64 = 5000;
+
+/// The most leds the 16 bit led count of the adalight header can represent
+const ADALIGHT_MAX_LEDS: u64 = 65536;
+
+/// The most leds the 16 bit start index of WLED DNRGB packets can address
+const WLED_MAX_LEDS: u64 = 65536;
+
+/// The last universe of E1.31, universes start at 1
+const SACN_MAX_UNIVERSE: u16 = 63999;
+
+/// The last 15 bit port-address of Art-Net, universes start at 0
+const ARTNET_MAX_UNIVERSE: u16 = 0x7fff;
+
+/// Milliseconds after which E1.31 receivers drop a source that stopped sending (network data loss, E1.31 section 6.7.1)
+const SACN_TIMEOUT: u64 = 2500;
+
+/// Milliseconds after which Art-Net nodes consider the data stale, the spec asks senders to refresh about every 4 seconds
+const ARTNET_TIMEOUT: u64 = 4000;
+
+///
+/// A problem found while validating the configuration
+///
+pub struct Problem {
+    /// The yaml path of the offending value, e.g. `connector.strips[1].mappings[0].length`
+    pub path: String,
+    /// The description of the problem
+    pub message: String
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+///
+/// A segment of a physical strip written by a mapping or mirror
+///
+struct Segment {
+    device_id: u64,
+    physical_strip_idx: u8,
+    leds: Range<u32>,
+    path: String
+}
+
+///
+/// Validates the configuration
+///
+/// # Arguments
+///
+/// * `config` - The configuration to validate
+///
+/// # Returns
+///
+/// Every problem found in the configuration, in the order of the file
+///
+pub fn validate(config: &Configuration) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut problem = |path: String, message: String| problems.push(Problem { path, message });
+
+    // connector devices
+    check_duplicates(config.connector.devices.iter().map(|device| device.id), "connector.devices", &mut problem);
+    for (i, device) in config.connector.devices.iter().enumerate() {
+        if device.physical_strips.is_empty() {
+            problem(format!("connector.devices[{}].physical_strips", i), "device has no physical strips".to_string());
+        }
+        if let Sink::Sacn { priority, .. } = &device.sink {
+            if *priority > 200 {
+                problem(format!("connector.devices[{}].priority", i), format!("priority {} is not between 0 and 200", priority));
+            }
+        }
+
+        // the sink has to be able to address every led of the device
+        let universes = |last: u16, first: u16| (last - first) as u64 + 1;
+        let capacity = match &device.sink {
+            Sink::Serial { protocol: SerialProtocol::Raw, .. } => None,
+            Sink::Serial { protocol: SerialProtocol::Adalight, .. } => Some((ADALIGHT_MAX_LEDS, "the adalight header")),
+            Sink::Wled { .. } => Some((WLED_MAX_LEDS, "the dnrgb start index")),
+            Sink::Sacn { universe, .. } if *universe == 0 || *universe > SACN_MAX_UNIVERSE => {
+                problem(format!("connector.devices[{}].universe", i), format!("universe {} is not between 1 and {}", universe, SACN_MAX_UNIVERSE));
+                None
+            },
+            Sink::Sacn { universe, .. } => Some((universes(SACN_MAX_UNIVERSE, *universe) * LEDS_PER_UNIVERSE as u64, "the remaining universes")),
+            Sink::Artnet { universe, .. } if *universe > ARTNET_MAX_UNIVERSE => {
+                problem(format!("connector.devices[{}].universe", i), format!("universe {} is not between 0 and {}", universe, ARTNET_MAX_UNIVERSE));
+                None
+            },
+            Sink::Artnet { universe, .. } => Some((universes(ARTNET_MAX_UNIVERSE, *universe) * LEDS_PER_UNIVERSE as u64, "the remaining universes"))
+        };
+        let leds = device.physical_strips.iter().map(|strip| strip.leds as u64).sum::<u64>();
+        if let Some((max, limit)) = capacity.filter(|(max, _)| leds > *max) {
+            problem(format!("connector.devices[{}].physical_strips", i), format!("physical strips add up to {} leds, but {} can only address {} leds", leds, limit, max));
+        }
+
+        // devices return to their own mode if they go without a frame for too long
+        match &device.sink {
+            Sink::Serial { .. } if config.connector.keepalive >= SERIAL_TIMEOUT => {
+                problem("connector.keepalive".to_string(), format!("keepalive of {} ms reaches the serial timeout of {} ms of device {}", config.connector.keepalive, SERIAL_TIMEOUT, device.id));
+            },
+            Sink::Wled { timeout, .. } if *timeout != 255 && config.connector.keepalive >= *timeout as u64 * 1000 => {
+                problem("connector.keepalive".to_string(), format!("keepalive of {} ms reaches the timeout of {} s of device {}", config.connector.keepalive, timeout, device.id));
+            },
+            Sink::Sacn { .. } if config.connector.keepalive >= SACN_TIMEOUT => {
+                problem("connector.keepalive".to_string(), format!("keepalive of {} ms reaches the network data loss timeout of {} ms of device {}", config.connector.keepalive, SACN_TIMEOUT, device.id));
+            },
+            Sink::Artnet { .. } if config.connector.keepalive >= ARTNET_TIMEOUT => {
+                problem("connector.keepalive".to_string(), format!("keepalive of {} ms reaches the refresh interval of {} ms of device {}", config.connector.keepalive, ARTNET_TIMEOUT, device.id));
+            },
+            _ => ()
+        }
+    }
+    let mut devices = HashMap::new();
+    for device in &config.connector.devices {
+        devices.entry(device.id).or_insert(device); // duplicates were already reported
+    }
+
+    // connector strips
+    check_duplicates(config.connector.strips.iter().map(|strip| strip.id), "connector.strips", &mut problem);
+    let mut segments = Vec::new();
+    for (i, strip) in config.connector.strips.iter().enumerate() {
+        let sum = strip.mappings.iter().map(|mapping| mapping.length as u32).sum::<u32>();
+        if sum != strip.leds as u32 {
+            problem(format!("connector.strips[{}].mappings", i), format!("mapping lengths add up to {} leds, but the strip has {} leds", sum, strip.leds));
+        }
+
+        for (j, mapping) in strip.mappings.iter().enumerate() {
+            let path = format!("connector.strips[{}].mappings[{}]", i, j);
+            let targets = std::iter::once((path.clone(), mapping.device_id, mapping.physical_strip_idx, mapping.offset))
+                .chain(mapping.mirrors.iter().enumerate().map(|(k, mirror)|
+                    (format!("{}.mirrors[{}]", path, k), mirror.device_id, mirror.physical_strip_idx, mirror.offset)
+                ));
+
+            for (path, device_id, physical_strip_idx, offset) in targets {
+                let Some(device) = devices.get(&device_id) else {
+                    problem(format!("{}.device_id", path), format!("device {} is not defined", device_id));
+                    continue;
+                };
+                let Some(physical_strip) = device.physical_strips.get(physical_strip_idx as usize) else {
+                    problem(format!("{}.physical_strip_idx", path), format!("device {} only has {} physical strip(s)", device_id, device.physical_strips.len()));
+                    continue;
+                };
+
+                let leds = offset as u32..offset as u32 + mapping.length as u32;
+                if leds.end > physical_strip.leds as u32 {
+                    problem(path, format!("leds {} to {} overrun physical strip {} of device {}, which has {} leds", leds.start, leds.end - 1, physical_strip_idx, device_id, physical_strip.leds));
+                    continue;
+                }
+
+                segments.push(Segment { device_id, physical_strip_idx, leds, path });
+            }
+        }
+    }
+
+    // overlapping segments on the same physical strip
+    segments.sort_by_key(|segment| (segment.device_id, segment.physical_strip_idx, segment.leds.start));
+    let mut furthest: Option<&Segment> = None; // segment reaching furthest on the current physical strip
+    for segment in &segments {
+        match furthest {
+            Some(other) if other.device_id == segment.device_id && other.physical_strip_idx == segment.physical_strip_idx => {
+                if segment.leds.start < other.leds.end {
+                    problem(segment.path.clone(), format!("overlaps with {} on physical strip {} of device {}", other.path, segment.physical_strip_idx, segment.device_id));
+                }
+                if segment.leds.end > other.leds.end {
+                    furthest = Some(segment);
+                }
+            },
+            _ => furthest = Some(segment)
+        }
+    }
+
+    // idle
+    if config.screencopy.idle.as_ref().is_some_and(|idle| idle.timeout == 0) {
+        problem("screencopy.idle.timeout".to_string(), "idle timeout must be at least 1 second".to_string());
+    }
+
+    // capture sessions
+    check_duplicates(config.screencopy.capture_sessions.iter().map(|session| session.id), "screencopy.capture_sessions", &mut problem);
+    for (i, session) in config.screencopy.capture_sessions.iter().enumerate() {
+        if session.id >= 1 << 63 {
+            problem(format!("screencopy.capture_sessions[{}].id", i), format!("id {} is reserved for shared captures of entire outputs", session.id));
+        }
+        if session.output.as_ref().is_some_and(|output| output.0.is_empty()) {
+            problem(format!("screencopy.capture_sessions[{}].output", i), "no output selector is specified, omit the output to capture the global layout".to_string());
+        }
+        for (j, selector) in session.output.iter().flat_map(|output| output.0.iter()).enumerate() {
+            if selector.properties().next().is_none() {
+                problem(format!("screencopy.capture_sessions[{}].output[{}]", i, j), "output selector does not specify any property".to_string());
+            }
+        }
+        let lengths = match &session.region {
+            Region::Edge { depth, .. } => vec![("depth", depth)],
+            Region::Rectangle { width, height, .. } => vec![("width", width), ("height", height)]
+        };
+        for (name, length) in lengths {
+            let path = format!("screencopy.capture_sessions[{}].region.{}", i, name);
+            match length {
+                Length::Absolute(length) if *length <= 0 => problem(path, format!("region has an empty {} of {}", name, length)),
+                Length::Relative(percent) if *percent <= 0.0 || *percent > 100.0 => problem(path, format!("relative {} of {}% is not between 0% and 100%", name, percent)),
+                _ => ()
+            }
+        }
+    }
+    let sessions = config.screencopy.capture_sessions.iter().map(|session| session.id).collect::<HashSet<_>>();
+
+    // programs
+    check_duplicates(config.render_pipeline.programs.iter().map(|program| program.id), "render_pipeline.programs", &mut problem);
+    for (i, program) in config.render_pipeline.programs.iter().enumerate() {
+        if !config.connector.strips.iter().any(|strip| strip.id == program.strip_id) {
+            problem(format!("render_pipeline.programs[{}].strip_id", i), format!("strip {} is not defined", program.strip_id));
+        }
+
+        for (j, session) in program.capture_sessions.iter().enumerate() {
+            if !sessions.contains(session) {
+                problem(format!("render_pipeline.programs[{}].capture_sessions[{}]", i, j), format!("capture session {} is not defined", session));
+            }
+        }
+    }
+
+    if config.fps == 0 {
+        problem("fps".to_string(), "frame rate must be greater than 0".to_string());
+    }
+
+    problems
+}
+
+///
+/// Reports every id that was already used by a previous entry of the list
+///
+/// # Arguments
+///
+/// * `ids` - The ids of the list in order
+/// * `path` - The yaml path of the list
+/// * `problem` - The problem callback
+///
+fn check_duplicates(ids: impl Iterator<Item = u64>, path: &str, problem: &mut impl FnMut(String, String)) {
+    let mut seen = HashMap::new();
+    for (i, id) in ids.enumerate() {
+        match seen.get(&id) {
+            Some(first) => problem(format!("{}[{}].id", path, i), format!("id {} is already used by {}[{}]", id, path, first)),
+            None => { seen.insert(id, i); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Configuration;
+
+    const CONFIG: &str = "
+connector:
+  devices:
+    - id: 1
+      port: /dev/ttyUSB0
+      baud_rate: 500000
+      physical_strips:
+        - leds: 20
+        - leds: 30
+  strips:
+    - id: 1
+      leds: 20
+      mappings:
+        - device_id: 1
+          physical_strip_idx: 0
+          offset: 0
+          length: 20
+    - id: 2
+      leds: 30
+      mappings:
+        - device_id: 1
+          physical_strip_idx: 1
+          offset: 0
+          length: 10
+        - device_id: 1
+          physical_strip_idx: 1
+          offset: 10
+          length: 20
+screencopy:
+  capture_sessions:
+    - id: 1
+      output: DP-1
+      region:
+        edge: top
+        depth: 10%
+render_pipeline:
+  programs:
+    - id: 1
+      vertex_shader: shaders/left_to_right.vert
+      fragment_shader: shaders/ws2812b.frag
+      capture_sessions: [1]
+      strip_id: 1
+fps: 60
+log_level: info
+";
+
+    const DEVICE: &str = "    - id: 1\n      port: /dev/ttyUSB0\n      baud_rate: 500000\n      physical_strips:\n        - leds: 20\n        - leds: 30\n";
+
+    const SESSION: &str = "    - id: 1\n      output: DP-1\n      region:\n        edge: top\n        depth: 10%\n";
+
+    ///
+    /// Validates the configuration above with the replacements applied and returns the paths of the problems
+    ///
+    fn problems(replacements: &[(&str, &str)]) -> Vec<String> {
+        let mut yaml = CONFIG.to_string();
+        for (from, to) in replacements {
+            assert!(yaml.contains(from), "'{}' is not part of the configuration", from);
+            yaml = yaml.replacen(from, to, 1);
+        }
+        let config: Configuration = serde_yml::from_str(&yaml).unwrap();
+        config.validate().into_iter().map(|problem| problem.path).collect()
+    }
+
+    #[test]
+    fn valid() {
+        assert!(problems(&[]).is_empty());
+    }
+
+    #[test]
+    fn duplicate_device_id() {
+        assert_eq!(problems(&[(DEVICE, &DEVICE.repeat(2))]), ["connector.devices[1].id"]);
+    }
+
+    #[test]
+    fn duplicate_strip_id() {
+        assert_eq!(problems(&[("- id: 2\n      leds: 30", "- id: 1\n      leds: 30")]), ["connector.strips[1].id"]);
+    }
+
+    #[test]
+    fn duplicate_capture_session_id() {
+        assert_eq!(problems(&[(SESSION, &SESSION.repeat(2))]), ["screencopy.capture_sessions[1].id"]);
+    }
+
+    #[test]
+    fn duplicate_program_id() {
+        assert_eq!(problems(&[("strip_id: 1\n", "strip_id: 1\n    - id: 1\n      vertex_shader: a\n      fragment_shader: b\n      capture_sessions: []\n      strip_id: 2\n")]), ["render_pipeline.programs[1].id"]);
+    }
+
+    #[test]
+    fn device_without_physical_strips() {
+        let device = "    - id: 2\n      port: /dev/ttyUSB1\n      baud_rate: 500000\n      physical_strips: []\n";
+        assert_eq!(problems(&[("  strips:\n", &format!("{}  strips:\n", device))]), ["connector.devices[1].physical_strips"]);
+    }
+
+    #[test]
+    fn adalight_led_count_overflow() {
+        let replacements = [("baud_rate: 500000", "baud_rate: 500000\n      protocol: adalight"), ("- leds: 30\n", "- leds: 30\n        - leds: 65535\n")];
+        assert_eq!(problems(&replacements), ["connector.devices[0].physical_strips"]);
+        assert!(problems(&replacements[..1]).is_empty());
+    }
+
+    #[test]
+    fn wled_led_count_overflow() {
+        let replacements = [("port: /dev/ttyUSB0\n      baud_rate: 500000", "type: wled\n      address: 10.0.0.1"), ("- leds: 30\n", "- leds: 30\n        - leds: 65535\n")];
+        assert_eq!(problems(&replacements), ["connector.devices[0].physical_strips"]);
+        assert!(problems(&replacements[..1]).is_empty());
+    }
+
+    #[test]
+    fn universes_exhausted() {
+        let sacn = [("port: /dev/ttyUSB0\n      baud_rate: 500000", "type: sacn\n      universe: 63999"), ("- leds: 30\n", "- leds: 151\n")];
+        assert_eq!(problems(&sacn), ["connector.devices[0].physical_strips"]);
+        let artnet = [("port: /dev/ttyUSB0\n      baud_rate: 500000", "type: artnet\n      address: 10.0.0.1\n      universe: 32767"), ("- leds: 30\n", "- leds: 151\n")];
+        assert_eq!(problems(&artnet), ["connector.devices[0].physical_strips"]);
+        assert!(problems(&artnet[..1]).is_empty());
+    }
+
+    #[test]
+    fn universe_out_of_range() {
+        assert_eq!(problems(&[("port: /dev/ttyUSB0\n      baud_rate: 500000", "type: sacn\n      universe: 0")]), ["connector.devices[0].universe"]);
+        assert_eq!(problems(&[("port: /dev/ttyUSB0\n      baud_rate: 500000", "type: artnet\n      address: 10.0.0.1\n      universe: 32768")]), ["connector.devices[0].universe"]);
+    }
+
+    #[test]
+    fn sacn_priority_out_of_range() {
+        assert_eq!(problems(&[("port: /dev/ttyUSB0\n      baud_rate: 500000", "type: sacn\n      priority: 201")]), ["connector.devices[0].priority"]);
+        assert!(problems(&[("port: /dev/ttyUSB0\n      baud_rate: 500000", "type: sacn\n      priority: 200")]).is_empty());
+    }
+
+    #[test]
+    fn keepalive_reaches_serial_timeout() {
+        assert_eq!(problems(&[("  strips:\n", "  keepalive: 5000\n  strips:\n")]), ["connector.keepalive"]);
+    }
+
+    #[test]
+    fn keepalive_reaches_sacn_timeout() {
+        let replacements = [("port: /dev/ttyUSB0\n      baud_rate: 500000", "type: sacn"), ("  strips:\n", "  keepalive: 2500\n  strips:\n")];
+        assert_eq!(problems(&replacements), ["connector.keepalive"]);
+        assert!(problems(&replacements[..1]).is_empty());
+    }
+
+    #[test]
+    fn keepalive_reaches_artnet_timeout() {
+        let replacements = [("port: /dev/ttyUSB0\n      baud_rate: 500000", "type: artnet\n      address: 10.0.0.1"), ("  strips:\n", "  keepalive: 4000\n  strips:\n")];
+        assert_eq!(problems(&replacements), ["connector.keepalive"]);
+    }
+
+    #[test]
+    fn undefined_device() {
+        assert_eq!(problems(&[("device_id: 1", "device_id: 2")]), ["connector.strips[0].mappings[0].device_id"]);
+    }
+
+    #[test]
+    fn undefined_physical_strip() {
+        assert_eq!(problems(&[("physical_strip_idx: 0", "physical_strip_idx: 2")]), ["connector.strips[0].mappings[0].physical_strip_idx"]);
+    }
+
+    #[test]
+    fn undefined_strip() {
+        assert_eq!(problems(&[("strip_id: 1", "strip_id: 3")]), ["render_pipeline.programs[0].strip_id"]);
+    }
+
+    #[test]
+    fn undefined_capture_session() {
+        assert_eq!(problems(&[("capture_sessions: [1]", "capture_sessions: [1, 2]")]), ["render_pipeline.programs[0].capture_sessions[1]"]);
+    }
+
+    #[test]
+    fn mapping_lengths_differ_from_strip() {
+        assert_eq!(problems(&[("leds: 30\n      mappings", "leds: 31\n      mappings")]), ["connector.strips[1].mappings"]);
+    }
+
+    #[test]
+    fn mapping_overruns_physical_strip() {
+        assert_eq!(problems(&[("offset: 10", "offset: 11")]), ["connector.strips[1].mappings[1]"]);
+    }
+
+    #[test]
+    fn mappings_overlap() {
+        assert_eq!(problems(&[("offset: 10", "offset: 9")]), ["connector.strips[1].mappings[1]"]);
+    }
+
+    #[test]
+    fn mirror_overlaps_mappings() {
+        let mirror = "length: 20\n          mirrors:\n            - device_id: 1\n              physical_strip_idx: 1\n              offset: 5\n    - id: 2";
+        assert_eq!(
+            problems(&[("length: 20\n    - id: 2", mirror)]),
+            ["connector.strips[0].mappings[0].mirrors[0]", "connector.strips[1].mappings[1]"]
+        );
+    }
+
+    #[test]
+    fn empty_region() {
+        assert_eq!(problems(&[("depth: 10%", "depth: 0")]), ["screencopy.capture_sessions[0].region.depth"]);
+        assert_eq!(problems(&[("depth: 10%", "depth: 101%")]), ["screencopy.capture_sessions[0].region.depth"]);
+    }
+
+    #[test]
+    fn reserved_capture_session_id() {
+        assert_eq!(
+            problems(&[("- id: 1\n      output", "- id: 9223372036854775808\n      output")]),
+            ["screencopy.capture_sessions[0].id", "render_pipeline.programs[0].capture_sessions[0]"]
+        );
+    }
+
+    #[test]
+    fn zero_idle_timeout() {
+        assert_eq!(problems(&[("  capture_sessions:\n", "  idle:\n    timeout: 0\n  capture_sessions:\n")]), ["screencopy.idle.timeout"]);
+    }
+
+    #[test]
+    fn output_selector_without_properties() {
+        assert_eq!(problems(&[("output: DP-1", "output: [{}]")]), ["screencopy.capture_sessions[0].output[0]"]);
+    }
+
+    #[test]
+    fn empty_output_selectors() {
+        assert_eq!(problems(&[("output: DP-1", "output: []")]), ["screencopy.capture_sessions[0].output"]);
+    }
+
+    #[test]
+    fn zero_fps() {
+        assert_eq!(problems(&[("fps: 60", "fps: 0")]), ["fps"]);
+    }
+}
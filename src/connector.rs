@@ -1,18 +1,44 @@
-use std::collections::HashMap;
+use std::{collections::{HashMap, HashSet}, time::{Duration, Instant}};
 
 use anyhow::Context;
 use device::Device;
-use log::trace;
+use log::{info, trace, warn};
 use strip::Strip;
 
+pub mod artnet;
 pub mod device;
+pub mod e131;
+pub mod serial;
+pub mod sink;
 pub mod strip;
+pub mod wled;
+
+///
+/// Exponential backoff for reconnecting failed devices
+///
+pub struct Backoff {
+    pub initial: Duration, // delay after the first failure
+    pub max: Duration // upper bound of the delay
+}
+
+///
+/// Health of a device
+///
+enum Health {
+    Connecting { attempts: u32 }, // the writer thread is opening the sink, after the given amount of failed attempts
+    Connected,
+    Disconnected { attempts: u32, retry_at: Instant }
+}
 
 ///
 /// Connector between multiple devices and virtual led strips
 pub struct Connector {
     devices: HashMap<u64, Device>,
+    health: HashMap<u64, Health>,
     strips: HashMap<u64, Strip>,
+    backoff: Backoff,
+    keepalive: Duration, // longest time a device goes without a frame
+    written: HashMap<u64, Instant> // when each device was last written
 }
 
 impl Connector {
@@ -20,20 +46,70 @@ impl Connector {
     ///
     /// Create a new connector
     ///
-    pub fn new() -> Self {
-        Self { devices: HashMap::new(), strips: HashMap::new() }
+    /// # Arguments
+    ///
+    /// * `backoff` - Backoff for reconnecting failed devices
+    /// * `keepalive` - Longest time a device goes without a frame while its strips are unchanged
+    ///
+    pub fn new(backoff: Backoff, keepalive: Duration) -> Self {
+        Self { devices: HashMap::new(), health: HashMap::new(), strips: HashMap::new(), backoff, keepalive, written: HashMap::new() }
+    }
+
+    ///
+    /// Update the backoff for reconnecting failed devices
+    ///
+    /// # Arguments
+    ///
+    /// * `backoff` - Backoff for reconnecting failed devices
+    ///
+    pub fn set_backoff(&mut self, backoff: Backoff) {
+        self.backoff = backoff;
     }
 
     ///
-    /// Add a new device to the connector
+    /// Update the keepalive of the devices
+    ///
+    /// # Arguments
+    ///
+    /// * `keepalive` - Longest time a device goes without a frame while its strips are unchanged
+    ///
+    pub fn set_keepalive(&mut self, keepalive: Duration) {
+        self.keepalive = keepalive;
+    }
+
+    ///
+    /// Add a new device to the connector and connect it
+    ///
+    /// The device is connected in the background. If it cannot be connected, it is retried with exponential backoff while writing.
+    /// A device replacing another one is only connected once the writer thread of the replaced device exited.
     ///
     /// # Arguments
     ///
     /// * `id` - Id of the device
     /// * `device` - Device
     ///
-    pub fn set_device(&mut self, id: u64, device: Device) {
+    pub fn set_device(&mut self, id: u64, mut device: Device) {
+        if let Some(previous) = self.devices.remove(&id) {
+            device.succeed(previous);
+        }
+        device.connect();
+
         self.devices.insert(id, device);
+        self.health.insert(id, Health::Connecting { attempts: 0 });
+        self.written.remove(&id);
+    }
+
+    ///
+    /// Remove a device from the connector, disconnecting it
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the device
+    ///
+    pub fn remove_device(&mut self, id: u64) {
+        self.devices.remove(&id);
+        self.health.remove(&id);
+        self.written.remove(&id);
     }
 
     ///
@@ -48,6 +124,17 @@ impl Connector {
         self.strips.insert(id, strip);
     }
 
+    ///
+    /// Remove a strip from the connector
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the strip
+    ///
+    pub fn remove_strip(&mut self, id: u64) {
+        self.strips.remove(&id);
+    }
+
     ///
     /// Get a mutable reference to a strip
     ///
@@ -64,35 +151,149 @@ impl Connector {
     }
 
     ///
-    /// Write all strips to the devices
+    /// Write the changed strips to the devices
+    ///
+    /// Only devices with changed strips receive a frame, unless they went without one for longer than the keepalive.
+    /// The devices are written by their writer threads, so this function does not block on slow devices.
+    /// Devices that fail or hang in a write are disconnected and reconnected with exponential backoff, while all other devices keep receiving frames.
     ///
     /// # Errors
     ///
-    /// This function returns an error if any of the strips or devices fail to write
+    /// This function returns an error if any of the strips fail to write
     ///
     pub fn write(&mut self) -> Result<(), anyhow::Error> {
-        // copy all virtual led strips to the devices
-        for (id, strip) in &self.strips {
+        // copy the changed virtual led strips to the devices
+        let mut changed = HashSet::new();
+        for (id, strip) in self.strips.iter_mut().filter(|(_, strip)| strip.is_changed()) {
             strip.write(&mut self.devices).context("failed to write strip")?;
+            changed.extend(strip.device_ids());
             trace!("copied virtual strip {} to physical strips", id);
         }
 
-        // write all devices
+        // hand the buffers to all healthy devices and reconnect failed ones
+        let now = Instant::now();
         for (id, device) in &mut self.devices {
-            device.write().context("failed to write device")?;
-            trace!("wrote device {}", id);
+            let health = self.health.get_mut(id).unwrap();
+            match health {
+                Health::Disconnected { retry_at, .. } if now < *retry_at => continue,
+                Health::Disconnected { attempts, .. } => {
+                    trace!("reconnecting device {}", id);
+                    device.connect();
+                    *health = Health::Connecting { attempts: *attempts };
+                    self.written.remove(id);
+                },
+                Health::Connecting { attempts } if device.is_open() => {
+                    if *attempts > 0 {
+                        info!("reconnected device {} after {} attempt(s)", id, attempts);
+                    }
+                    *health = Health::Connected;
+                },
+                _ => ()
+            }
+
+            // skip devices whose strips are unchanged until the keepalive is due
+            let due = self.written.get(id).is_none_or(|written| now.duration_since(*written) >= self.keepalive);
+            if !due && !changed.contains(id) {
+                continue;
+            }
+
+            match device.write() {
+                Ok(()) => {
+                    trace!("queued frame for device {}", id);
+                    self.written.insert(*id, now);
+                },
+                Err(e) => {
+                    let attempts = match health {
+                        Health::Connecting { attempts } => *attempts,
+                        _ => 0
+                    };
+                    let delay = self.backoff.delay(attempts);
+                    if attempts == 0 {
+                        warn!("failed to write device {}, reconnecting in {:?}: {:?}", id, delay, e);
+                    } else {
+                        warn!("failed to reconnect device {}, retrying in {:?}: {:?}", id, delay, e);
+                    }
+                    device.disconnect();
+                    *health = Health::Disconnected { attempts: attempts + 1, retry_at: now + delay };
+                }
+            }
         }
 
         trace!("finished writing all devices");
         Ok(())
     }
 
+}
+
+impl Backoff {
+
+    ///
+    /// Get the delay before the next attempt
+    ///
+    /// # Arguments
+    ///
+    /// * `attempts` - Amount of failed attempts so far
+    ///
+    fn delay(&self, attempts: u32) -> Duration {
+        self.initial.saturating_mul(2u32.saturating_pow(attempts)).min(self.max)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{atomic::{AtomicUsize, Ordering}, mpsc::{self, Receiver, Sender}, Arc, Mutex}, thread, time::Duration};
+
+    use super::{device::{Device, Opener}, sink::Sink, Backoff, Connector};
+
     ///
-    /// Reset the connector
+    /// Sink reporting every write and blocking it until it is released
     ///
-    pub fn reset(&mut self) {
-        self.devices.clear();
-        self.strips.clear();
+    struct Blocking {
+        entered: Sender<()>,
+        release: Arc<Mutex<Receiver<()>>>
+    }
+
+    impl Sink for Blocking {
+        fn write(&mut self, _: &[u8]) -> Result<(), anyhow::Error> {
+            let _ = self.entered.send(());
+            let _ = self.release.lock().unwrap().recv();
+            Ok(())
+        }
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn reload_waits_for_blocked_writer() {
+        let ((entered, writes), (release, releases)) = (mpsc::channel(), mpsc::channel());
+        let (releases, opened) = (Arc::new(Mutex::new(releases)), Arc::new(AtomicUsize::new(0)));
+        let open_count = opened.clone();
+        let open: Opener = Arc::new(move || {
+            open_count.fetch_add(1, Ordering::SeqCst);
+            Ok(Box::new(Blocking { entered: entered.clone(), release: releases.clone() }) as Box<dyn Sink>)
+        });
+        let mut connector = Connector::new(Backoff { initial: Duration::ZERO, max: Duration::ZERO }, Duration::ZERO);
+
+        // block the writer thread of the device in the sink
+        connector.set_device(1, Device::new(open.clone(), vec![1]));
+        while writes.try_recv().is_err() {
+            connector.write().unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // the reloaded device must not open the sink while the previous writer thread is blocked in it
+        connector.set_device(1, Device::new(open.clone(), vec![2]));
+        for _ in 0..10 {
+            connector.write().unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(opened.load(Ordering::SeqCst), 1);
+
+        // once the sink returns, the reloaded device connects
+        release.send(()).unwrap();
+        while opened.load(Ordering::SeqCst) < 2 {
+            connector.write().unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+        drop(release); // unblock the writes of the reloaded device
+    }
+}
@@ -0,0 +1,136 @@
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use anyhow::{anyhow, Context};
+
+use super::{e131::LEDS_PER_UNIVERSE, sink::Sink};
+
+/// Default port of Art-Net
+const PORT: u16 = 6454;
+/// OpDmx operation code
+const OP_DMX: u16 = 0x5000;
+/// Protocol revision
+const PROTOCOL_VERSION: u16 = 14;
+
+///
+/// Art-Net sink
+///
+pub struct ArtNet {
+    socket: UdpSocket,
+    target: SocketAddr,
+    universe: u16,
+    sequence: u8,
+    packet: Vec<u8>
+}
+
+impl ArtNet {
+
+    ///
+    /// Create a new Art-Net sink
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Unicast or broadcast address of the receiver
+    /// * `universe` - First port-address (net, sub-net and universe), following universes are used for buffers larger than 170 leds
+    /// * `universes` - Amount of universes required for the buffer
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the socket could not be bound or the address could not be resolved
+    ///
+    pub fn new(address: &str, universe: u16, universes: u16) -> Result<Self, anyhow::Error> {
+        if universe.checked_add(universes.saturating_sub(1)).is_none_or(|last| last > 0x7fff) {
+            return Err(anyhow!("universes must be within 0 and 32767"));
+        }
+
+        let target = (address, PORT).to_socket_addrs().context("failed to resolve address")?.next().context("address did not resolve")?;
+        let socket = UdpSocket::bind(if target.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" }).context("failed to bind udp socket")?;
+        if target.is_ipv4() {
+            socket.set_broadcast(true).context("failed to enable broadcast")?;
+        }
+        Ok(Self {
+            socket,
+            target,
+            universe,
+            sequence: 0,
+            packet: Vec::with_capacity(18 + LEDS_PER_UNIVERSE * 3)
+        })
+    }
+
+    ///
+    /// Build an ArtDmx packet into the packet buffer
+    ///
+    /// # Arguments
+    ///
+    /// * `universe` - 15 bit port-address of the packet
+    /// * `data` - Dmx data of the universe (at most 512 channels)
+    ///
+    fn build_packet(&mut self, universe: u16, data: &[u8]) {
+        let p = &mut self.packet;
+        p.clear();
+        p.extend_from_slice(b"Art-Net\0");
+        p.extend_from_slice(&OP_DMX.to_le_bytes());
+        p.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+        p.push(self.sequence);
+        p.push(0); // physical
+        p.extend_from_slice(&universe.to_le_bytes()); // sub-uni and net
+        let length = data.len().next_multiple_of(2); // length must be even
+        p.extend_from_slice(&(length as u16).to_be_bytes());
+        p.extend_from_slice(data);
+        p.resize(18 + length, 0);
+    }
+
+}
+
+impl Sink for ArtNet {
+
+    ///
+    /// Send the data as ArtDmx packets, one per universe
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if any packet could not be sent
+    ///
+    fn write(&mut self, buffer: &[u8]) -> Result<(), anyhow::Error> {
+        self.sequence = self.sequence % 255 + 1; // 0 disables sequencing
+        for (i, chunk) in buffer.chunks(LEDS_PER_UNIVERSE * 3).enumerate() {
+            self.build_packet(self.universe + i as u16, chunk);
+            self.socket.send_to(&self.packet, self.target).context("failed to send art-net packet")?;
+        }
+
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::sink::Sink, ArtNet};
+
+    #[test]
+    fn dmx_packet() {
+        let mut sink = ArtNet::new("127.0.0.1", 0, 1).unwrap();
+        sink.sequence = 9;
+        sink.build_packet(0x1234, &[1, 2, 3]);
+        assert_eq!(sink.packet, [
+            b'A', b'r', b't', b'-', b'N', b'e', b't', 0, // id
+            0x00, 0x50, // OpDmx, little endian
+            0, 14, // protocol version, big endian
+            9, 0, // sequence and physical
+            0x34, 0x12, // sub-uni and net
+            0, 4, // length, big endian and padded to an even amount
+            1, 2, 3, 0
+        ]);
+    }
+
+    #[test]
+    fn ipv6_address() {
+        let mut sink = ArtNet::new("::1", 0, 1).unwrap();
+        sink.write(&[1, 2, 3]).unwrap();
+    }
+
+    #[test]
+    fn universe_range() {
+        assert!(ArtNet::new("127.0.0.1", 0x7fff, 2).is_err());
+        assert!(ArtNet::new("127.0.0.1", 0x7fff, 1).is_ok());
+    }
+}
@@ -1,13 +1,43 @@
-use std::{io, path::PathBuf};
+use std::{sync::{Arc, Condvar, Mutex}, thread::{self, JoinHandle}, time::{Duration, Instant}};
 
-use anyhow::Context;
-use serial2::SerialPort;
+use anyhow::{anyhow, Context};
+use log::{debug, trace};
+
+use super::sink::Sink;
+
+/// Longest time a single write to the sink may take before the sink is considered hung
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Longest time opening the sink may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
 ///
-/// Serial device
+/// Frame slot shared between a device and its writer thread
+///
+#[derive(Default)]
+struct Slot {
+    frame: Option<Vec<u8>>, // latest frame that has not been written yet
+    error: Option<anyhow::Error>, // last error reported by the writer thread
+    deadline: Option<Instant>, // time by which the connection or write in progress has to finish
+    open: bool, // whether the writer thread opened the sink
+    closed: bool // whether the device was dropped
+}
+
+///
+/// Function that opens the sink of a device, called on the writer thread whenever the device (re)connects
+///
+pub type Opener = Arc<dyn Fn() -> Result<Box<dyn Sink>, anyhow::Error> + Send + Sync>;
+
+///
+/// Device consisting of one or more physical strips and an output sink
+///
+/// While connected, the sink is opened and driven by a dedicated writer thread, so a slow or hung sink, or a hostname
+/// that takes long to resolve, never blocks the caller. Only the latest frame is kept, frames that were not written in time are dropped.
 ///
 pub struct Device {
-    serial: SerialPort,
+    open: Opener,
+    slot: Option<Arc<(Mutex<Slot>, Condvar)>>, // shared with the writer thread while connected
+    thread: Option<JoinHandle<()>>, // writer thread of the current or previous connection
     buffer: Vec<u8>,
     lengths: Vec<u16>
 }
@@ -15,24 +45,128 @@ pub struct Device {
 impl Device {
 
     ///
-    /// Create a new serial device
+    /// Create a new disconnected device
     ///
     /// # Arguments
     ///
-    /// * `port` - Path to the serial port
-    /// * `baud_rate` - Baud rate
+    /// * `open` - Function opening the sink the buffer is written to
     /// * `lengths` - Amount of leds per strip connected to this device
     ///
-    /// # Errors
+    pub fn new(open: Opener, lengths: Vec<u16>) -> Self {
+        Self {
+            open,
+            slot: None,
+            thread: None,
+            buffer: vec![0; lengths.iter().map(|length| *length as usize).sum::<usize>() * 3],
+            lengths,
+        }
+    }
+
     ///
-    /// This function returns an error if the serial port could not be opened
+    /// Spawn the writer thread opening the sink, disconnecting first if necessary
     ///
-    pub fn new(port: &PathBuf, baud_rate: u32, lengths: Vec<u16>) -> Result<Self, anyhow::Error> {
-        Ok(Self {
-            serial: SerialPort::open(port, baud_rate).context("failed to open serial port")?,
-            buffer: vec![0; lengths.iter().sum::<u16>() as usize * 3],
-            lengths,
-        })
+    /// No writer thread is spawned while the writer thread of the previous connection is still blocked in the sink, so two threads
+    /// never write to the same sink and hung sinks do not pile up threads. Failing to open the sink is reported by `write`, just like failed writes.
+    ///
+    pub(super) fn connect(&mut self) {
+        self.disconnect();
+
+        if let Some(previous) = self.thread.take() {
+            if !previous.is_finished() {
+                let error = anyhow!("writer thread of the previous connection is still blocked in the sink");
+                self.slot = Some(Arc::new((Mutex::new(Slot { error: Some(error), ..Default::default() }), Condvar::new())));
+                self.thread = Some(previous);
+                return;
+            }
+            let _ = previous.join();
+        }
+
+        let slot = Arc::new((Mutex::new(Slot { deadline: Some(Instant::now() + CONNECT_TIMEOUT), ..Default::default() }), Condvar::new()));
+        let (thread_slot, open) = (slot.clone(), self.open.clone());
+        self.thread = Some(thread::spawn(move || Device::run(thread_slot, open)));
+
+        self.slot = Some(slot);
+    }
+
+    ///
+    /// Take over the writer thread of the device this device replaces
+    ///
+    /// The replaced device is disconnected and `connect` holds off until its writer thread exited, as both devices may drive the same sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous` - The replaced device
+    ///
+    pub(super) fn succeed(&mut self, mut previous: Device) {
+        previous.disconnect();
+        self.thread = previous.thread.take();
+    }
+
+    ///
+    /// Check whether the writer thread opened the sink
+    ///
+    pub(super) fn is_open(&self) -> bool {
+        self.slot.as_ref().is_some_and(|slot| slot.0.lock().unwrap().open)
+    }
+
+    ///
+    /// Stop the writer thread and close the sink
+    ///
+    /// The writer thread is not joined, as a hung sink would otherwise block the caller. `connect` checks whether it exited instead.
+    ///
+    pub(super) fn disconnect(&mut self) {
+        if let Some(slot) = self.slot.take() {
+            let (lock, cvar) = &*slot;
+            lock.lock().unwrap().closed = true;
+            cvar.notify_one();
+        }
+    }
+
+    ///
+    /// Writer thread, opens the sink and writes the latest frame to it until the device is dropped
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - Frame slot shared with the device
+    /// * `open` - Function opening the sink the frames are written to
+    ///
+    fn run(slot: Arc<(Mutex<Slot>, Condvar)>, open: Opener) {
+        let (lock, cvar) = &*slot;
+        let mut sink = match open() {
+            Ok(sink) => sink,
+            Err(error) => {
+                let mut slot = lock.lock().unwrap();
+                slot.deadline = None;
+                slot.error = Some(error.context("failed to open sink"));
+                return;
+            }
+        };
+        {
+            let mut slot = lock.lock().unwrap();
+            slot.deadline = None;
+            slot.open = true;
+        }
+        debug!("writer thread opened sink");
+
+        loop {
+            let frame = {
+                let mut slot = cvar.wait_while(lock.lock().unwrap(), |slot| slot.frame.is_none() && !slot.closed).unwrap();
+                if slot.closed {
+                    break;
+                }
+                slot.deadline = Some(Instant::now() + WRITE_TIMEOUT);
+                slot.frame.take().unwrap()
+            };
+
+            let result = sink.write(&frame);
+            let mut slot = lock.lock().unwrap();
+            slot.deadline = None;
+            if let Err(error) = result {
+                slot.error = Some(error);
+            }
+        }
+
+        trace!("writer thread exited");
     }
 
     ///
@@ -45,21 +179,105 @@ impl Device {
     /// * `length - Length of the subarray
     ///
     pub(super) fn get_mut(&mut self, strip: u8, offset: u16, length: u16) -> &mut [u8] {
-        let start = self.lengths.iter().take(strip as usize).sum::<u16>() as usize * 3 + offset as usize * 3;
+        let start = self.lengths.iter().take(strip as usize).map(|length| *length as usize).sum::<usize>() * 3 + offset as usize * 3;
         &mut self.buffer[start..start + length as usize * 3]
     }
 
     ///
-    /// Write the data to the serial port
+    /// Hand the buffer to the writer thread (this does NOT wait for the buffer to be written)
     ///
     /// # Errors
     ///
-    /// This function returns an error if the data could not be written or flushed to the serial port
+    /// This function returns an error if the device is not connected, the writer thread failed to write a previous frame
+    /// or a previous write is overdue, i.e. the sink hung
     ///
-    pub(super) fn write(&self) -> Result<(), io::Error> {
-        self.serial.write_all(&self.buffer)?;
-        self.serial.flush()?;
+    pub(super) fn write(&mut self) -> Result<(), anyhow::Error> {
+        let (lock, cvar) = &**self.slot.as_ref().context("device is not connected")?;
+        let mut slot = lock.lock().unwrap();
+        if let Some(error) = slot.error.take() {
+            return Err(error);
+        }
+        if slot.deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            return Err(anyhow!("{} did not finish in time", if slot.open { "write to sink" } else { "opening the sink" }));
+        }
+
+        if slot.frame.replace(self.buffer.clone()).is_some() {
+            trace!("dropped stale frame, writer thread is behind");
+        }
+        cvar.notify_one();
+
         Ok(())
     }
 
 }
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{atomic::{AtomicUsize, Ordering}, mpsc::{self, Receiver}, Arc, Mutex}, thread, time::Duration};
+
+    use super::{super::sink::Sink, Device};
+
+    ///
+    /// Sink blocking every write until it is released
+    ///
+    struct Blocking(Arc<Mutex<Receiver<()>>>);
+
+    impl Sink for Blocking {
+        fn write(&mut self, _: &[u8]) -> Result<(), anyhow::Error> {
+            let _ = self.0.lock().unwrap().recv();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn no_reconnect_while_writer_is_blocked() {
+        let (release, receiver) = mpsc::channel();
+        let (receiver, opened) = (Arc::new(Mutex::new(receiver)), Arc::new(AtomicUsize::new(0)));
+        let open_count = opened.clone();
+        let mut device = Device::new(Arc::new(move || {
+            open_count.fetch_add(1, Ordering::SeqCst);
+            Ok(Box::new(Blocking(receiver.clone())) as Box<dyn Sink>)
+        }), vec![1]);
+
+        device.connect();
+        while !device.is_open() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        device.write().unwrap();
+        while device.slot.as_ref().unwrap().0.lock().unwrap().deadline.is_none() {
+            thread::sleep(Duration::from_millis(1)); // wait for the writer thread to block in the sink
+        }
+
+        // the blocked writer thread is kept instead of spawning another one
+        device.disconnect();
+        device.connect();
+        assert!(device.write().is_err());
+        assert_eq!(opened.load(Ordering::SeqCst), 1);
+
+        // once the sink returns, the writer thread exits and the device reconnects
+        release.send(()).unwrap();
+        while !device.thread.as_ref().unwrap().is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        device.disconnect();
+        device.connect();
+        while !device.is_open() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(opened.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn more_than_65535_leds() {
+        let mut device = Device::new(Arc::new(|| Err(anyhow::anyhow!("not connected"))), vec![40000, 40000]);
+        assert_eq!(device.buffer.len(), 240000);
+        let start = device.get_mut(1, 39999, 1).as_ptr() as usize - device.buffer.as_ptr() as usize;
+        assert_eq!(start, 239997);
+    }
+}
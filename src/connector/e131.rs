@@ -0,0 +1,187 @@
+use std::{collections::hash_map::RandomState, hash::{BuildHasher, Hasher}, net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket}};
+
+use anyhow::{anyhow, Context};
+
+use super::sink::Sink;
+
+/// Default port of E1.31
+const PORT: u16 = 5568;
+/// Amount of rgb leds that fit into a single universe
+pub const LEDS_PER_UNIVERSE: usize = 170;
+
+///
+/// E1.31 (sACN) sink
+///
+pub struct E131 {
+    socket: UdpSocket,
+    targets: Vec<SocketAddr>, // one target per universe
+    universe: u16,
+    priority: u8,
+    source_name: [u8; 64],
+    cid: [u8; 16],
+    sequence: u8,
+    packet: Vec<u8>
+}
+
+impl E131 {
+
+    ///
+    /// Create a new E1.31 sink
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Unicast address of the receiver, if none is specified each universe is sent to its multicast group
+    /// * `universe` - First universe, following universes are used for buffers larger than 170 leds
+    /// * `universes` - Amount of universes required for the buffer
+    /// * `priority` - Priority of the source (0-200)
+    /// * `source_name` - User facing name of the source
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the socket could not be bound or the address could not be resolved
+    ///
+    pub fn new(address: Option<&str>, universe: u16, universes: u16, priority: u8, source_name: &str) -> Result<Self, anyhow::Error> {
+        if universe == 0 || universe.checked_add(universes.saturating_sub(1)).is_none_or(|last| last > 63999) {
+            return Err(anyhow!("universes must be within 1 and 63999"));
+        }
+
+        let targets = match address {
+            Some(address) => {
+                let target = (address, PORT).to_socket_addrs().context("failed to resolve address")?.next().context("address did not resolve")?;
+                vec![target; universes as usize]
+            },
+            None => (universe..universe + universes).map(|u| {
+                let [hi, lo] = u.to_be_bytes();
+                SocketAddr::from((Ipv4Addr::new(239, 255, hi, lo), PORT))
+            }).collect()
+        };
+
+        let mut name = [0; 64];
+        let len = source_name.len().min(63);
+        name[..len].copy_from_slice(&source_name.as_bytes()[..len]);
+
+        // the cid only has to be unique per source, so random bytes are good enough
+        let mut cid = [0; 16];
+        for half in cid.chunks_mut(8) {
+            half.copy_from_slice(&RandomState::new().build_hasher().finish().to_be_bytes());
+        }
+
+        let ipv6 = targets.first().is_some_and(SocketAddr::is_ipv6);
+        let socket = UdpSocket::bind(if ipv6 { "[::]:0" } else { "0.0.0.0:0" }).context("failed to bind udp socket")?;
+        Ok(Self {
+            socket,
+            targets, universe, priority, source_name: name, cid, sequence: 0,
+            packet: Vec::with_capacity(126 + LEDS_PER_UNIVERSE * 3)
+        })
+    }
+
+    ///
+    /// Build an E1.31 data packet into the packet buffer
+    ///
+    /// # Arguments
+    ///
+    /// * `universe` - Universe of the packet
+    /// * `data` - Dmx data of the universe (at most 512 channels)
+    ///
+    fn build_packet(&mut self, universe: u16, data: &[u8]) {
+        let length = 126 + data.len() as u16;
+        let p = &mut self.packet;
+        p.clear();
+
+        // root layer
+        p.extend_from_slice(&0x0010u16.to_be_bytes()); // preamble size
+        p.extend_from_slice(&0x0000u16.to_be_bytes()); // postamble size
+        p.extend_from_slice(b"ASC-E1.17\0\0\0"); // acn packet identifier
+        p.extend_from_slice(&(0x7000 | (length - 16)).to_be_bytes()); // flags and length
+        p.extend_from_slice(&0x00000004u32.to_be_bytes()); // VECTOR_ROOT_E131_DATA
+        p.extend_from_slice(&self.cid);
+
+        // framing layer
+        p.extend_from_slice(&(0x7000 | (length - 38)).to_be_bytes()); // flags and length
+        p.extend_from_slice(&0x00000002u32.to_be_bytes()); // VECTOR_E131_DATA_PACKET
+        p.extend_from_slice(&self.source_name);
+        p.push(self.priority);
+        p.extend_from_slice(&0u16.to_be_bytes()); // synchronization address
+        p.push(self.sequence);
+        p.push(0); // options
+        p.extend_from_slice(&universe.to_be_bytes());
+
+        // dmp layer
+        p.extend_from_slice(&(0x7000 | (length - 115)).to_be_bytes()); // flags and length
+        p.push(0x02); // VECTOR_DMP_SET_PROPERTY
+        p.push(0xa1); // address type and data type
+        p.extend_from_slice(&0u16.to_be_bytes()); // first property address
+        p.extend_from_slice(&1u16.to_be_bytes()); // address increment
+        p.extend_from_slice(&(data.len() as u16 + 1).to_be_bytes()); // property value count
+        p.push(0x00); // dmx start code
+        p.extend_from_slice(data);
+    }
+
+}
+
+impl Sink for E131 {
+
+    ///
+    /// Send the data as E1.31 packets, one per universe
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if any packet could not be sent
+    ///
+    fn write(&mut self, buffer: &[u8]) -> Result<(), anyhow::Error> {
+        self.sequence = self.sequence.wrapping_add(1);
+        for (i, chunk) in buffer.chunks(LEDS_PER_UNIVERSE * 3).enumerate() {
+            self.build_packet(self.universe + i as u16, chunk);
+            self.socket.send_to(&self.packet, self.targets[i]).context("failed to send e1.31 packet")?;
+        }
+
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::sink::Sink, E131};
+
+    #[test]
+    fn data_packet() {
+        let mut sink = E131::new(Some("127.0.0.1"), 1, 1, 150, "test").unwrap();
+        sink.sequence = 7;
+        sink.build_packet(258, &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let p = &sink.packet;
+
+        assert_eq!(p.len(), 135);
+        // root layer
+        assert_eq!(p[0..16], *b"\x00\x10\x00\x00ASC-E1.17\0\0\0");
+        assert_eq!(p[16..22], [0x70, 0x77, 0, 0, 0, 4]);
+        assert_eq!(p[22..38], sink.cid);
+        // framing layer
+        assert_eq!(p[38..44], [0x70, 0x61, 0, 0, 0, 2]);
+        assert_eq!(p[44..49], *b"test\0");
+        assert!(p[49..108].iter().all(|byte| *byte == 0));
+        assert_eq!(p[108..115], [150, 0, 0, 7, 0, 1, 2]);
+        // dmp layer
+        assert_eq!(p[115..126], [0x70, 0x14, 0x02, 0xa1, 0, 0, 0, 1, 0, 10, 0]);
+        assert_eq!(p[126..], [1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn multicast_targets() {
+        let sink = E131::new(None, 255, 2, 100, "test").unwrap();
+        assert_eq!(sink.targets, ["239.255.0.255:5568".parse().unwrap(), "239.255.1.0:5568".parse().unwrap()]);
+    }
+
+    #[test]
+    fn ipv6_address() {
+        let mut sink = E131::new(Some("::1"), 1, 1, 100, "test").unwrap();
+        sink.write(&[1, 2, 3]).unwrap();
+    }
+
+    #[test]
+    fn universe_range() {
+        assert!(E131::new(None, 0, 1, 100, "test").is_err());
+        assert!(E131::new(None, 63999, 2, 100, "test").is_err());
+        assert!(E131::new(None, 63999, 1, 100, "test").is_ok());
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serial2::SerialPort;
+
+use super::sink::Sink;
+
+///
+/// Framing of the data sent over the serial port
+///
+#[derive(Clone, Copy)]
+pub enum Protocol {
+    /// Raw rgb bytes without any framing
+    Raw,
+    /// Adalight header consisting of the magic word, the led count and a checksum, followed by the rgb bytes
+    Adalight
+}
+
+///
+/// Serial sink
+///
+pub struct Serial {
+    serial: SerialPort,
+    protocol: Protocol
+}
+
+impl Serial {
+
+    ///
+    /// Create a new serial sink
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - Path to the serial port
+    /// * `baud_rate` - Baud rate
+    /// * `protocol` - Framing of the data
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the serial port could not be opened
+    ///
+    pub fn new(port: &PathBuf, baud_rate: u32, protocol: Protocol) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            serial: SerialPort::open(port, baud_rate).context("failed to open serial port")?,
+            protocol
+        })
+    }
+
+}
+
+impl Sink for Serial {
+
+    ///
+    /// Write the data to the serial port
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the data could not be written or flushed to the serial port
+    ///
+    fn write(&mut self, buffer: &[u8]) -> Result<(), anyhow::Error> {
+        if let Protocol::Adalight = self.protocol {
+            let [hi, lo] = ((buffer.len() / 3).saturating_sub(1) as u16).to_be_bytes();
+            self.serial.write_all(&[b'A', b'd', b'a', hi, lo, hi ^ lo ^ 0x55]).context("failed to write adalight header")?;
+        }
+
+        self.serial.write_all(buffer).context("failed to write to serial port")?;
+        self.serial.flush().context("failed to flush serial port")?;
+        Ok(())
+    }
+
+}
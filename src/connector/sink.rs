@@ -0,0 +1,22 @@
+///
+/// Output sink of a device
+///
+/// A sink receives the flat rgb buffer of a device and is responsible for transferring it to the actual hardware,
+/// e.g. over a serial port or the network. Sinks are moved to the writer thread of their device.
+///
+pub trait Sink: Send {
+
+    ///
+    /// Write a buffer to the sink
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Rgb buffer containing all leds of all physical strips of the device
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the buffer could not be transferred
+    ///
+    fn write(&mut self, buffer: &[u8]) -> Result<(), anyhow::Error>;
+
+}
@@ -5,18 +5,34 @@ use anyhow::Context;
 use super::device::Device;
 
 ///
-/// LED strip mapping
+/// Physical target of a mapping
 ///
-pub struct Mapping {
+pub struct Target {
     device_id: u64,
     strip_id: u8,
     offset: u16, // where on the physical strip this segment starts
+    reverse: bool // whether the segment runs in the opposite direction on the physical strip
+}
+
+impl Target {
+    pub fn new(device_id: u64, strip_id: u8, offset: u16, reverse: bool) -> Self {
+        Self { device_id, strip_id, offset, reverse }
+    }
+}
+
+///
+/// LED strip mapping
+///
+/// A mapping copies a segment of the virtual strip to one or more physical targets, which allows mirroring a segment.
+///
+pub struct Mapping {
+    targets: Vec<Target>,
     length: u16
 }
 
 impl Mapping {
-    pub fn new(device_id: u64, strip_id: u8, offset: u16, length: u16) -> Self {
-        Self { device_id, strip_id, offset, length }
+    pub fn new(targets: Vec<Target>, length: u16) -> Self {
+        Self { targets, length }
     }
 }
 
@@ -25,7 +41,8 @@ impl Mapping {
 ///
 pub struct Strip {
     mappings: Vec<Mapping>,
-    buffer: Vec<u8>
+    buffer: Vec<u8>,
+    changed: bool // whether the buffer may have changed since it was last copied to the devices
 }
 
 impl Strip {
@@ -40,14 +57,15 @@ impl Strip {
     pub fn new(length: u16) -> Self {
         Self {
             mappings: Vec::new(),
-            buffer: vec![0; length as usize * 3]
+            buffer: vec![0; length as usize * 3],
+            changed: true
         }
     }
 
     ///
     /// Map a part of the group to a strip (call this method in order)
     ///
-    /// Inverting the direction of a strip is not supported and should be done in the renderer
+    /// Each target of the mapping can be reversed individually, in which case the first led of the segment ends up at the end of the target.
     ///
     /// # Arguments
     ///
@@ -61,9 +79,24 @@ impl Strip {
     /// Get a mutable reference to the buffer
     ///
     pub fn get_mut(&mut self) -> &mut [u8] {
+        self.changed = true;
         &mut self.buffer
     }
 
+    ///
+    /// Check whether the buffer may have changed since it was last copied to the devices
+    ///
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    ///
+    /// Get the ids of all devices the strip is mapped to
+    ///
+    pub fn device_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.mappings.iter().flat_map(|mapping| mapping.targets.iter().map(|target| target.device_id))
+    }
+
     ///
     /// Copy the buffer to the devices (this does NOT write the buffer to the devices)
     ///
@@ -75,16 +108,78 @@ impl Strip {
     ///
     /// This function returns an error if a device could not be found in the device map
     ///
-    pub(super) fn write(&self, device_map: &mut HashMap<u64, Device>) -> Result<(), anyhow::Error> {
+    pub(super) fn write(&mut self, device_map: &mut HashMap<u64, Device>) -> Result<(), anyhow::Error> {
         let mut offset = 0;
         for mapping in &self.mappings {
-            let device = device_map.get_mut(&mapping.device_id).context("device not found in device id map")?;
-            let buffer_slice = device.get_mut(mapping.strip_id, mapping.offset, mapping.length);
-            buffer_slice.copy_from_slice(&self.buffer[offset..offset + mapping.length as usize * 3]);
+            let segment = &self.buffer[offset..offset + mapping.length as usize * 3];
+            for target in &mapping.targets {
+                let device = device_map.get_mut(&target.device_id).context("device not found in device id map")?;
+                let buffer_slice = device.get_mut(target.strip_id, target.offset, mapping.length);
+                if target.reverse {
+                    for (dst, src) in buffer_slice.chunks_exact_mut(3).zip(segment.chunks_exact(3).rev()) {
+                        dst.copy_from_slice(src);
+                    }
+                } else {
+                    buffer_slice.copy_from_slice(segment);
+                }
+            }
             offset += mapping.length as usize * 3;
         }
 
+        self.changed = false;
         Ok(())
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::{super::device::Device, Mapping, Strip, Target};
+
+    ///
+    /// Create a strip whose n-th led (counting from 1) has the color n, n + 10, n + 20 and write it to a device with the given physical strips
+    ///
+    fn write(leds: u16, mappings: Vec<Mapping>, lengths: Vec<u16>) -> Device {
+        let mut strip = Strip::new(leds);
+        for (i, led) in strip.get_mut().chunks_exact_mut(3).enumerate() {
+            led.copy_from_slice(&[i as u8 + 1, i as u8 + 11, i as u8 + 21]);
+        }
+        for mapping in mappings {
+            strip.map(mapping);
+        }
+
+        let mut devices = HashMap::from([(1, Device::new(Arc::new(|| Err(anyhow::anyhow!("not connected"))), lengths))]);
+        strip.write(&mut devices).unwrap();
+        devices.remove(&1).unwrap()
+    }
+
+    #[test]
+    fn reversed_segment_at_offset() {
+        let mut device = write(4, vec![
+            Mapping::new(vec![Target::new(1, 0, 0, false)], 1),
+            Mapping::new(vec![Target::new(1, 0, 2, true)], 3)
+        ], vec![6]);
+        assert_eq!(device.get_mut(0, 0, 6), [1, 11, 21, 0, 0, 0, 4, 14, 24, 3, 13, 23, 2, 12, 22, 0, 0, 0]);
+    }
+
+    #[test]
+    fn mirror_onto_second_physical_strip() {
+        let mut device = write(3, vec![
+            Mapping::new(vec![Target::new(1, 0, 0, false), Target::new(1, 1, 1, false)], 3)
+        ], vec![3, 5]);
+        assert_eq!(device.get_mut(0, 0, 3), [1, 11, 21, 2, 12, 22, 3, 13, 23]);
+        assert_eq!(device.get_mut(1, 0, 5), [0, 0, 0, 1, 11, 21, 2, 12, 22, 3, 13, 23, 0, 0, 0]);
+    }
+
+    #[test]
+    fn reversed_mirror() {
+        let mut device = write(4, vec![
+            Mapping::new(vec![Target::new(1, 0, 0, false), Target::new(1, 1, 0, true)], 2),
+            Mapping::new(vec![Target::new(1, 0, 2, true), Target::new(1, 1, 2, false)], 2)
+        ], vec![4, 4]);
+        assert_eq!(device.get_mut(0, 0, 4), [1, 11, 21, 2, 12, 22, 4, 14, 24, 3, 13, 23]);
+        assert_eq!(device.get_mut(1, 0, 4), [2, 12, 22, 1, 11, 21, 3, 13, 23, 4, 14, 24]);
+    }
+}
@@ -0,0 +1,132 @@
+use std::net::{ToSocketAddrs, UdpSocket};
+
+use anyhow::Context;
+
+use super::sink::Sink;
+
+/// DRGB protocol identifier, colors for all leds starting at index 0
+const DRGB: u8 = 2;
+/// DNRGB protocol identifier, colors for all leds starting at a given index
+const DNRGB: u8 = 4;
+/// Maximum amount of leds per packet before the buffer is split into DNRGB chunks
+const MAX_LEDS_PER_PACKET: usize = 489;
+
+///
+/// WLED realtime UDP sink
+///
+pub struct Wled {
+    socket: UdpSocket,
+    timeout: u8,
+    packet: Vec<u8>
+}
+
+impl Wled {
+
+    ///
+    /// Create a new WLED sink
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Hostname or ip address of the WLED controller
+    /// * `port` - Realtime UDP port of the WLED controller (usually 21324)
+    /// * `timeout` - Seconds the controller waits after the last packet before returning to normal mode (255 disables the timeout)
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the socket could not be bound or the address could not be resolved
+    ///
+    pub fn new(address: &str, port: u16, timeout: u8) -> Result<Self, anyhow::Error> {
+        let target = (address, port).to_socket_addrs().context("failed to resolve address")?.next().context("address did not resolve")?;
+        let socket = UdpSocket::bind(if target.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" }).context("failed to bind udp socket")?;
+        socket.connect(target).context("failed to connect udp socket")?;
+        Ok(Self { socket, timeout, packet: Vec::with_capacity(4 + MAX_LEDS_PER_PACKET * 3) })
+    }
+
+}
+
+impl Sink for Wled {
+
+    ///
+    /// Send the data as WLED realtime packets
+    ///
+    /// Buffers of up to 489 leds are sent as a single DRGB packet, larger buffers are split into DNRGB packets.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if any packet could not be sent
+    ///
+    fn write(&mut self, buffer: &[u8]) -> Result<(), anyhow::Error> {
+        if buffer.len() <= MAX_LEDS_PER_PACKET * 3 {
+            self.packet.clear();
+            self.packet.extend_from_slice(&[DRGB, self.timeout]);
+            self.packet.extend_from_slice(buffer);
+            self.socket.send(&self.packet).context("failed to send drgb packet")?;
+            return Ok(());
+        }
+
+        for (i, chunk) in buffer.chunks(MAX_LEDS_PER_PACKET * 3).enumerate() {
+            let start = (i * MAX_LEDS_PER_PACKET) as u16;
+            self.packet.clear();
+            self.packet.extend_from_slice(&[DNRGB, self.timeout]);
+            self.packet.extend_from_slice(&start.to_be_bytes());
+            self.packet.extend_from_slice(chunk);
+            self.socket.send(&self.packet).context("failed to send dnrgb packet")?;
+        }
+
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::UdpSocket, time::Duration};
+
+    use super::{super::sink::Sink, Wled, MAX_LEDS_PER_PACKET};
+
+    ///
+    /// Create a WLED sink sending to a local socket
+    ///
+    fn connect(address: &str) -> (Wled, UdpSocket) {
+        let receiver = UdpSocket::bind((address, 0)).unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let sink = Wled::new(address, receiver.local_addr().unwrap().port(), 5).unwrap();
+        (sink, receiver)
+    }
+
+    #[test]
+    fn drgb_packet() {
+        let (mut sink, receiver) = connect("127.0.0.1");
+        sink.write(&[1, 2, 3, 4, 5, 6]).unwrap();
+
+        let mut packet = [0; 2048];
+        let len = receiver.recv(&mut packet).unwrap();
+        assert_eq!(packet[..len], [2, 5, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn ipv6_address() {
+        let (mut sink, receiver) = connect("::1");
+        sink.write(&[1, 2, 3]).unwrap();
+
+        let mut packet = [0; 2048];
+        let len = receiver.recv(&mut packet).unwrap();
+        assert_eq!(packet[..len], [2, 5, 1, 2, 3]);
+    }
+
+    #[test]
+    fn dnrgb_packets() {
+        let (mut sink, receiver) = connect("127.0.0.1");
+        let buffer = (0..(MAX_LEDS_PER_PACKET + 1) * 3).map(|i| i as u8).collect::<Vec<_>>();
+        sink.write(&buffer).unwrap();
+
+        let mut packet = [0; 2048];
+        let len = receiver.recv(&mut packet).unwrap();
+        assert_eq!(len, 4 + MAX_LEDS_PER_PACKET * 3);
+        assert_eq!(packet[..4], [4, 5, 0, 0]);
+        assert_eq!(packet[4..len], buffer[..MAX_LEDS_PER_PACKET * 3]);
+
+        let len = receiver.recv(&mut packet).unwrap();
+        assert_eq!(packet[..len], [4, 5, 0x01, 0xe9, buffer[buffer.len() - 3], buffer[buffer.len() - 2], buffer[buffer.len() - 1]]);
+    }
+}
@@ -1,126 +1,1120 @@
-use std::sync::mpsc::channel;
-use std::{path::PathBuf, str::FromStr};
+use std::collections::{HashMap, HashSet};
+use std::sync::{mpsc::channel, Arc};
+use std::time::{Duration, Instant, SystemTime};
+use std::{path::{Path, PathBuf}, str::FromStr};
 
 use anyhow::{anyhow, Context};
 use log::LevelFilter;
-use log::{info, warn};
-use notify::event::{DataChange, ModifyKind};
-use notify::{Config, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use log::{debug, info, warn};
+use notify::event::ModifyKind;
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use wayland_client::protocol::wl_output::WlOutput;
 
 use crate::configuration;
 use crate::connector;
 use crate::renderer;
 use crate::screencopy;
 
+/// Time the watched files have to stay unchanged before the configuration is reloaded
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Time a failed capture session is left alone before it is recreated, in case the compositor is frozen
+const CAPTURE_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+///
+/// Matches a text against a pattern with `*` (any text) and `?` (any character) wildcards.
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern.
+/// * `text` - The text to match.
+///
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.chars().collect::<Vec<char>>(), text.chars().collect::<Vec<char>>());
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None; // position of the last star in the pattern and the text position it currently covers
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if let Some((star, covered)) = backtrack {
+            backtrack = Some((star, covered + 1));
+            p = star + 1;
+            t = covered + 1;
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+///
+/// Checks whether an output matches every property specified by a selector.
+///
+/// # Arguments
+///
+/// * `selector` - The output selector.
+/// * `info` - The information of the output.
+///
+fn matches_selector(selector: &configuration::OutputSelector, info: &screencopy::OutputInfo) -> bool {
+    [
+        (&selector.name, &info.name),
+        (&selector.description, &info.description),
+        (&selector.make, &info.make),
+        (&selector.model, &info.model),
+        (&selector.serial, &info.serial)
+    ].iter().all(|(pattern, value)| match pattern {
+        Some(pattern) => value.as_deref().is_some_and(|value| matches_pattern(pattern, value)),
+        None => true
+    })
+}
+
+///
+/// Finds the output of a capture session by trying its selectors in order.
+///
+/// If a selector matches multiple outputs, the output with the lowest name is picked. A plain string selector matching
+/// no name falls back to a part of the description, which is how older versions matched outputs.
+///
+/// # Arguments
+///
+/// * `session` - The configuration of the capture session.
+/// * `screencopy` - The screencopy instance.
+///
+fn find_output(session: &configuration::CaptureSession, screencopy: &screencopy::Screencopy) -> Option<WlOutput> {
+    session.output.as_ref()?.0.iter().find_map(|selector| {
+        let by_selector = screencopy.outputs.iter()
+            .filter(|(_, info)| matches_selector(selector, info))
+            .min_by(|a, b| a.1.name.cmp(&b.1.name));
+        let by_description = || screencopy.outputs.iter()
+            .filter(|(_, info)| selector.plain && selector.name.as_deref().zip(info.description.as_deref()).is_some_and(|(name, description)| !name.is_empty() && description.contains(name)))
+            .min_by(|a, b| a.1.name.cmp(&b.1.name))
+            .inspect(|(_, info)| warn!("output {} of capture session {} matched the description of output {:?} instead of its name, use `description: \"*{}*\"` to keep matching it",
+                selector.name.as_deref().unwrap_or_default(), session.id, info.name, selector.name.as_deref().unwrap_or_default()));
+        by_selector.or_else(by_description).map(|(output, _)| output.clone())
+    })
+}
+
+///
+/// Gets the logical size of an output, preferring xdg-output over the mode and scale of the output.
+///
+/// # Arguments
+///
+/// * `info` - The information of the output.
+///
+fn logical_size(info: &screencopy::OutputInfo) -> Option<(i32, i32)> {
+    info.logical_size.or_else(|| info.mode.map(|(width, height, _)| {
+        let scale = info.scale.unwrap_or(1).max(1);
+        (width / scale, height / scale)
+    }))
+}
+
+///
+/// Gets the position of an output in the global layout, preferring xdg-output over the position reported by the output.
+///
+/// # Arguments
+///
+/// * `info` - The information of the output.
+///
+fn logical_position(info: &screencopy::OutputInfo) -> Option<(i32, i32)> {
+    info.logical_position.or(info.position)
+}
+
+///
+/// Describes what a capture session captures, for log messages.
+///
+/// # Arguments
+///
+/// * `session` - The configuration of the capture session.
+///
+fn target(session: &configuration::CaptureSession) -> String {
+    match &session.output {
+        Some(output) => format!("output {}", output),
+        None => "the global layout".to_string()
+    }
+}
+
+///
+/// A part of a region in the global layout that lies on a single output.
+///
+struct LayoutPart {
+    output: WlOutput,
+    region: (i32, i32, i32, i32), // region on the output in its local logical coordinates
+    rect: [f32; 4] // region of the composed texture it covers, relative to the size of the texture
+}
+
+///
+/// Resolves the region of a capture session in the global layout and splits it into its parts on each output.
+///
+/// The region is relative to the top-left corner of the bounding box of all outputs with a known position and size,
+/// and relative lengths are resolved against the size of that bounding box. Parts are ordered by the names of their outputs.
+///
+/// # Arguments
+///
+/// * `session` - The configuration of the capture session.
+/// * `screencopy` - The screencopy instance.
+///
+fn resolve_layout_region(session: &configuration::CaptureSession, screencopy: &screencopy::Screencopy) -> ((i32, i32, i32, i32), Vec<LayoutPart>) {
+    let mut outputs = screencopy.outputs.iter()
+        .filter_map(|(output, info)| Some((output, info, logical_position(info)?, logical_size(info)?)))
+        .collect::<Vec<_>>();
+    outputs.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+
+    // resolve the region against the bounding box of the layout
+    let left = outputs.iter().map(|(_, _, (x, _), _)| *x).min().unwrap_or(0);
+    let top = outputs.iter().map(|(_, _, (_, y), _)| *y).min().unwrap_or(0);
+    let right = outputs.iter().map(|(_, _, (x, _), (width, _))| x + width).max().unwrap_or(0);
+    let bottom = outputs.iter().map(|(_, _, (_, y), (_, height))| y + height).max().unwrap_or(0);
+    let (x, y, width, height) = session.region.resolve(right - left, bottom - top);
+    let (x, y) = (x + left, y + top);
+
+    // intersect the region with every output
+    let parts = outputs.into_iter().filter_map(|(output, _, (output_x, output_y), (output_width, output_height))| {
+        let (x0, y0) = (x.max(output_x), y.max(output_y));
+        let (x1, y1) = ((x + width).min(output_x + output_width), (y + height).min(output_y + output_height));
+        (x1 > x0 && y1 > y0).then(|| LayoutPart {
+            output: output.clone(),
+            region: (x0 - output_x, y0 - output_y, x1 - x0, y1 - y0),
+            rect: [
+                (x0 - x) as f32 / width as f32,
+                (y0 - y) as f32 / height as f32,
+                (x1 - x0) as f32 / width as f32,
+                (y1 - y0) as f32 / height as f32
+            ]
+        })
+    }).collect();
+
+    ((x - left, y - top, width, height), parts)
+}
+
+///
+/// Checks whether a capture session can be captured, i.e. its output is plugged in or its region in the global layout overlaps any output.
+///
+/// # Arguments
+///
+/// * `session` - The configuration of the capture session.
+/// * `screencopy` - The screencopy instance.
+///
+fn is_available(session: &configuration::CaptureSession, screencopy: &screencopy::Screencopy) -> bool {
+    match session.output {
+        Some(_) => find_output(session, screencopy).is_some(),
+        None => !resolve_layout_region(session, screencopy).1.is_empty()
+    }
+}
+
+///
+/// Resolves the region of a capture session against the logical size of its output.
+///
+/// # Arguments
+///
+/// * `session` - The configuration of the capture session.
+/// * `output` - The output of the capture session.
+/// * `screencopy` - The screencopy instance.
+///
+/// # Errors
+///
+/// Returns an error if the region is relative and the logical size of the output is unknown.
+///
+fn resolve_region(session: &configuration::CaptureSession, output: &WlOutput, screencopy: &screencopy::Screencopy) -> Result<(i32, i32, i32, i32), anyhow::Error> {
+    match screencopy.outputs.get(output).and_then(logical_size) {
+        Some((width, height)) => Ok(session.region.resolve(width, height)),
+        None if !session.region.is_relative() => Ok(session.region.resolve(0, 0)),
+        None => Err(anyhow!("logical size of output unknown, cannot resolve relative region"))
+    }
+}
+
+///
+/// A capture session and how its render texture is provided.
+///
+enum Capture {
+    Own(screencopy::CaptureSession, renderer::Source), // captured into its own texture
+    Region(screencopy::CaptureSession, u64, screencopy::RelativeRect), // cut from the shared session with the given id, sampling the given region of its texture
+    Composed(screencopy::CaptureSession, Vec<(u64, [f32; 4], [f32; 4])>, (u32, u32)) // composed of regions of shared sessions into a texture of the given size
+}
+
+///
+/// Shared sessions capturing entire outputs, with their id and render texture.
+///
+type SharedSessions = Vec<(u64, screencopy::CaptureSession, renderer::Source)>;
+
+///
+/// Creates the render texture of a prepared capture session.
+///
+/// # Arguments
+///
+/// * `capture_session` - The prepared capture session.
+/// * `render_pipeline` - The render pipeline instance.
+///
+/// # Errors
+///
+/// Returns an error if the capture session has no buffer or the texture cannot be created.
+///
+fn create_texture(capture_session: &screencopy::CaptureSession, render_pipeline: &renderer::RenderPipeline) -> Result<renderer::Source, anyhow::Error> {
+    Ok(match (capture_session.buffer_object(), capture_session.shm_buffer()) {
+        (Some(bo), _) => render_pipeline.create_texture(bo, capture_session.crop(), capture_session.transform()).context("failed to create texture")?,
+        (None, Some(shm)) => render_pipeline.create_shm_texture(shm.width, shm.height, capture_session.crop(), capture_session.transform()),
+        (None, None) => return Err(anyhow!("capture session has no buffer"))
+    })
+}
+
+///
+/// Prepares a capture session and creates its render texture.
+///
+/// If the dmabuf of the session cannot be imported, the session is prepared again with a linear dmabuf.
+///
+/// # Arguments
+///
+/// * `capture_session` - The capture session to prepare.
+/// * `screencopy` - The screencopy instance.
+/// * `render_pipeline` - The render pipeline instance.
+///
+/// # Errors
+///
+/// Returns an error if the capture session cannot be prepared or the texture cannot be created.
+///
+fn prepare_capture_session(capture_session: screencopy::CaptureSession, screencopy: &screencopy::Screencopy, render_pipeline: &renderer::RenderPipeline) -> Result<(screencopy::CaptureSession, renderer::Source), anyhow::Error> {
+    let capture_session = screencopy.prepare_capture_session(capture_session)?;
+    match create_texture(&capture_session, render_pipeline) {
+        Ok(texture) => Ok((capture_session, texture)),
+        Err(e) if capture_session.buffer_object().is_some() && !capture_session.is_linear() => {
+            warn!("failed to import dmabuf, retrying with a linear dmabuf: {:?}", e);
+            let capture_session = screencopy.prepare_capture_session(capture_session.linear())?;
+            let texture = create_texture(&capture_session, render_pipeline)?;
+            Ok((capture_session, texture))
+        },
+        Err(e) => Err(e)
+    }
+}
+
+///
+/// Checks whether the shared session of an output still captures all of it.
+///
+/// # Arguments
+///
+/// * `id` - The id of the shared session.
+/// * `output_size` - The logical size of the output.
+/// * `screencopy` - The screencopy instance.
+///
+fn is_shared_session_current(id: u64, output_size: (i32, i32), screencopy: &screencopy::Screencopy) -> bool {
+    screencopy.is_usable(id) && screencopy.region(id) == Some((0, 0, output_size.0, output_size.1))
+}
+
+///
+/// Checks whether the shared session of an output exists and still captures all of it.
+///
+/// # Arguments
+///
+/// * `output` - The output.
+/// * `screencopy` - The screencopy instance.
+///
+fn shared_session_current(output: &WlOutput, screencopy: &screencopy::Screencopy) -> bool {
+    let id = screencopy.shared_session_id(output);
+    let size = screencopy.outputs.get(output).and_then(logical_size);
+    id.zip(size).is_some_and(|(id, size)| is_shared_session_current(id, size, screencopy))
+}
+
+///
+/// Creates a capture session and its render texture.
+///
+/// If captures are shared, the session is cut from the shared session of its output instead. That shared session
+/// is only created if there is no current one yet, neither in the screencopy nor in `shared`.
+///
+/// # Arguments
+///
+/// * `session` - The configuration of the capture session.
+/// * `share` - Whether captures are shared between sessions on the same output.
+/// * `shared` - Shared sessions created but not set yet, new ones are added to it.
+/// * `screencopy` - The screencopy instance.
+/// * `render_pipeline` - The render pipeline instance.
+///
+/// # Errors
+///
+/// Returns an error if the output is not found, the capture session cannot be created or the texture cannot be created.
+///
+fn create_capture_session(session: &configuration::CaptureSession, share: bool, shared: &mut SharedSessions, screencopy: &screencopy::Screencopy, render_pipeline: &renderer::RenderPipeline) -> Result<Capture, anyhow::Error> {
+    if session.output.is_none() {
+        return create_composed_session(session, shared, screencopy, render_pipeline);
+    }
+
+    let output = find_output(session, screencopy).context("output not found")?;
+    let (left, top, width, height) = resolve_region(session, &output, screencopy)?;
+    debug!("resolved region of capture session {} to {}, {} with size {}x{}", session.id, left, top, width, height);
+
+    if !share {
+        // create the capture session and its render texture
+        let (capture_session, texture) = prepare_capture_session(screencopy::CaptureSession::new(output, left, top, width, height), screencopy, render_pipeline)
+            .context("failed to create capture session")?;
+        return Ok(Capture::Own(capture_session, texture));
+    }
+
+    // cut the region from the shared session of the output
+    let (source, output_size) = create_shared_session(&output, shared, screencopy, render_pipeline)?;
+    let rect = relative_rect((left, top, width, height), output_size);
+    Ok(Capture::Region(screencopy::CaptureSession::cut_from(source, output, left, top, width, height), source, rect))
+}
+
+///
+/// Creates the shared session of an output if there is no current one yet, neither in the screencopy nor in `shared`.
+///
+/// # Arguments
+///
+/// * `output` - The output.
+/// * `shared` - Shared sessions created but not set yet, new ones are added to it.
+/// * `screencopy` - The screencopy instance.
+/// * `render_pipeline` - The render pipeline instance.
+///
+/// # Errors
+///
+/// Returns an error if the output is not found, its logical size is unknown or the shared session cannot be created.
+///
+fn create_shared_session(output: &WlOutput, shared: &mut SharedSessions, screencopy: &screencopy::Screencopy, render_pipeline: &renderer::RenderPipeline) -> Result<(u64, (i32, i32)), anyhow::Error> {
+    let source = screencopy.shared_session_id(output).context("output not found")?;
+    let (output_width, output_height) = screencopy.outputs.get(output).and_then(logical_size).context("logical size of output unknown, cannot share its capture")?;
+    if !is_shared_session_current(source, (output_width, output_height), screencopy) && !shared.iter().any(|(id, _, _)| *id == source) {
+        debug!("creating shared capture session {:#x} for the entire {}x{} output", source, output_width, output_height);
+        let (capture_session, texture) = prepare_capture_session(screencopy::CaptureSession::new(output.clone(), 0, 0, output_width, output_height), screencopy, render_pipeline)
+            .context("failed to create shared capture session")?;
+        shared.push((source, capture_session, texture));
+    }
+
+    Ok((source, (output_width, output_height)))
+}
+
+///
+/// Converts a region of an output into x, y, width and height relative to the size of the output.
+///
+/// # Arguments
+///
+/// * `region` - The region in logical coordinates of the output.
+/// * `output_size` - The logical size of the output.
+///
+fn relative_rect(region: (i32, i32, i32, i32), output_size: (i32, i32)) -> screencopy::RelativeRect {
+    let relative = |value: i32, size: i32| (value as f32 / size.max(1) as f32).clamp(0.0, 1.0);
+    let (left, top, width, height) = region;
+    let (x, y) = (relative(left, output_size.0), relative(top, output_size.1));
+    (x, y, relative(width, output_size.0).min(1.0 - x), relative(height, output_size.1).min(1.0 - y))
+}
+
+///
+/// Creates a capture session whose region in the global layout is composed of the shared sessions of the outputs it overlaps.
+///
+/// # Arguments
+///
+/// * `session` - The configuration of the capture session.
+/// * `shared` - Shared sessions created but not set yet, new ones are added to it.
+/// * `screencopy` - The screencopy instance.
+/// * `render_pipeline` - The render pipeline instance.
+///
+/// # Errors
+///
+/// Returns an error if the region does not overlap any output or a shared session cannot be created.
+///
+fn create_composed_session(session: &configuration::CaptureSession, shared: &mut SharedSessions, screencopy: &screencopy::Screencopy, render_pipeline: &renderer::RenderPipeline) -> Result<Capture, anyhow::Error> {
+    let ((x, y, width, height), parts) = resolve_layout_region(session, screencopy);
+    if parts.is_empty() {
+        return Err(anyhow!("region does not overlap any output"));
+    }
+    debug!("resolved region of capture session {} to {}, {} with size {}x{} in the global layout, spanning {} output(s)", session.id, x, y, width, height, parts.len());
+
+    // the composed texture has the pixel density of the densest output, so HiDPI outputs are not downsampled
+    let density = parts.iter()
+        .filter_map(|part| screencopy.outputs.get(&part.output))
+        .filter_map(|info| info.mode.zip(logical_size(info)))
+        .map(|((mode_width, mode_height, _), (logical_width, logical_height))| mode_width.max(mode_height) as f32 / logical_width.max(logical_height).max(1) as f32)
+        .fold(1.0, f32::max);
+    let size = ((width as f32 * density).round() as u32, (height as f32 * density).round() as u32);
+
+    let mut sources = Vec::new();
+    let mut textures = Vec::new();
+    for LayoutPart { output, region, rect } in parts {
+        let (source, output_size) = create_shared_session(&output, shared, screencopy, render_pipeline)?;
+        let (u, v, w, h) = relative_rect(region, output_size);
+        textures.push((source, [u, v, w, h], rect));
+        sources.push((source, output, (u, v, w, h)));
+    }
+    Ok(Capture::Composed(screencopy::CaptureSession::compose(sources, x, y, width, height), textures, size))
+}
+
+///
+/// Sets created shared sessions and a capture session, replacing the previous ones with the same ids.
+///
+/// # Arguments
+///
+/// * `id` - The id of the capture session.
+/// * `capture` - The capture session.
+/// * `shared` - The shared sessions created along with it.
+/// * `screencopy` - The screencopy instance.
+/// * `render_pipeline` - The render pipeline instance.
+///
+fn set_capture_session(id: u64, capture: Capture, shared: SharedSessions, screencopy: &mut screencopy::Screencopy, render_pipeline: &mut renderer::RenderPipeline) {
+    for (source, capture_session, texture) in shared {
+        screencopy.set_capture_session(source, capture_session);
+        render_pipeline.set_texture(source, texture);
+    }
+    match capture {
+        Capture::Own(capture_session, texture) => {
+            screencopy.set_capture_session(id, capture_session);
+            render_pipeline.set_texture(id, texture);
+        },
+        Capture::Region(capture_session, source, rect) => {
+            screencopy.set_capture_session(id, capture_session);
+            render_pipeline.set_region(id, source, rect);
+        },
+        Capture::Composed(capture_session, parts, (width, height)) => {
+            screencopy.set_capture_session(id, capture_session);
+            render_pipeline.set_composite(id, width, height, parts);
+        }
+    }
+}
+
+///
+/// Recreates a capture session in place.
+///
+/// # Arguments
+///
+/// * `session` - The configuration of the capture session.
+/// * `share` - Whether captures are shared between sessions on the same output.
+/// * `screencopy` - The screencopy instance.
+/// * `render_pipeline` - The render pipeline instance.
+///
+/// # Errors
+///
+/// Returns an error if the capture session cannot be created.
+///
+fn recreate_capture_session(session: &configuration::CaptureSession, share: bool, screencopy: &mut screencopy::Screencopy, render_pipeline: &mut renderer::RenderPipeline) -> Result<(), anyhow::Error> {
+    let mut shared = Vec::new();
+    let capture = create_capture_session(session, share, &mut shared, screencopy, render_pipeline)?;
+    set_capture_session(session.id, capture, shared, screencopy, render_pipeline);
+
+    Ok(())
+}
+
+///
+/// Pauses a capture session whose output is gone, dropping its capture session and texture.
+///
+/// # Arguments
+///
+/// * `id` - The id of the capture session.
+/// * `paused` - The paused capture sessions.
+/// * `screencopy` - The screencopy instance.
+/// * `render_pipeline` - The render pipeline instance.
+///
+fn pause_capture_session(id: u64, paused: &mut HashSet<u64>, screencopy: &mut screencopy::Screencopy, render_pipeline: &mut renderer::RenderPipeline) {
+    screencopy.remove_capture_session(id);
+    render_pipeline.remove_texture(id);
+    paused.insert(id);
+}
+
+///
+/// Updates the capture sessions after outputs changed.
+///
+/// Sessions whose output was unplugged are paused and the ones whose output reappeared are resumed. Sessions are also recreated
+/// if a better matching output was plugged in or their relative region resolves differently, e.g. because the mode or scale changed, or the output was rotated.
+/// Sessions in the global layout are recreated whenever the outputs they span change.
+///
+/// # Arguments
+///
+/// * `config` - The configuration.
+/// * `paused` - The paused capture sessions.
+/// * `screencopy` - The screencopy instance.
+/// * `render_pipeline` - The render pipeline instance.
+///
+fn update_sessions(config: &configuration::Configuration, paused: &mut HashSet<u64>, screencopy: &mut screencopy::Screencopy, render_pipeline: &mut renderer::RenderPipeline) {
+    for session in &config.screencopy.capture_sessions {
+        if session.output.is_none() {
+            update_composed_session(session, paused, screencopy, render_pipeline);
+            continue;
+        }
+
+        let output = find_output(session, screencopy);
+        if !paused.contains(&session.id) {
+            if !screencopy.has_output(session.id) {
+                warn!("{} of capture session {} was removed, pausing", target(session), session.id);
+                pause_capture_session(session.id, paused, screencopy, render_pipeline);
+                continue;
+            }
+
+            // check whether the session still captures the right output and region
+            let outdated = output.as_ref().is_some_and(|output|
+                screencopy.output(session.id) != Some(output)
+                    || resolve_region(session, output, screencopy).ok() != screencopy.region(session.id)
+                    || screencopy.transform_changed(session.id)
+                    || (config.screencopy.share_captures && !shared_session_current(output, screencopy))
+            );
+            if outdated {
+                match recreate_capture_session(session, config.screencopy.share_captures, screencopy, render_pipeline) {
+                    Ok(()) => info!("{} of capture session {} changed, recreated capture session", target(session), session.id),
+                    Err(e) => warn!("failed to recreate capture session {}: {:?}", session.id, e)
+                }
+            }
+        } else if output.is_some() {
+            match recreate_capture_session(session, config.screencopy.share_captures, screencopy, render_pipeline) {
+                Ok(()) => {
+                    paused.remove(&session.id);
+                    info!("{} of capture session {} reappeared, resuming", target(session), session.id);
+                },
+                Err(e) => warn!("failed to resume capture session {}: {:?}", session.id, e)
+            }
+        }
+    }
+}
+
+///
+/// Updates a capture session in the global layout after outputs changed.
+///
+/// The session is paused once its region does not overlap any output anymore, and recreated whenever the outputs it spans,
+/// their regions or their shared sessions changed.
+///
+/// # Arguments
+///
+/// * `session` - The configuration of the capture session.
+/// * `paused` - The paused capture sessions.
+/// * `screencopy` - The screencopy instance.
+/// * `render_pipeline` - The render pipeline instance.
+///
+fn update_composed_session(session: &configuration::CaptureSession, paused: &mut HashSet<u64>, screencopy: &mut screencopy::Screencopy, render_pipeline: &mut renderer::RenderPipeline) {
+    let (region, parts) = resolve_layout_region(session, screencopy);
+    if parts.is_empty() {
+        if !paused.contains(&session.id) {
+            warn!("region of capture session {} does not overlap any output anymore, pausing", session.id);
+            pause_capture_session(session.id, paused, screencopy, render_pipeline);
+        }
+        return;
+    }
+
+    if paused.contains(&session.id) {
+        match recreate_capture_session(session, true, screencopy, render_pipeline) {
+            Ok(()) => {
+                paused.remove(&session.id);
+                info!("region of capture session {} overlaps an output again, resuming", session.id);
+            },
+            Err(e) => warn!("failed to resume capture session {}: {:?}", session.id, e)
+        }
+        return;
+    }
+
+    // check whether the session still spans the right outputs and regions
+    let sources = parts.iter().map(|part| {
+        let source = screencopy.shared_session_id(&part.output)?;
+        let output_size = screencopy.outputs.get(&part.output).and_then(logical_size)?;
+        Some((source, relative_rect(part.region, output_size)))
+    }).collect::<Option<Vec<_>>>();
+    let outdated = !screencopy.has_output(session.id)
+        || screencopy.region(session.id) != Some(region)
+        || sources.as_deref() != screencopy.parts(session.id)
+        || screencopy.transform_changed(session.id)
+        || parts.iter().any(|part| !shared_session_current(&part.output, screencopy));
+    if outdated {
+        match recreate_capture_session(session, true, screencopy, render_pipeline) {
+            Ok(()) => info!("outputs of capture session {} changed, recreated capture session", session.id),
+            Err(e) => warn!("failed to recreate capture session {}: {:?}", session.id, e)
+        }
+    }
+}
+
 ///
-/// (Re)creates a capture session.
+/// Collects the ids of the capture sessions that are neither paused nor on a powered off output.
 ///
 /// # Arguments
 ///
-/// * `session` - The configuration of the capture session.
+/// * `config` - The configuration.
+/// * `paused` - The paused capture sessions.
 /// * `screencopy` - The screencopy instance.
-/// * `render_pipeline` - The render pipeline instance.
 ///
-/// # Errors
+fn active_sessions(config: &configuration::Configuration, paused: &HashSet<u64>, screencopy: &screencopy::Screencopy) -> Vec<u64> {
+    config.screencopy.capture_sessions.iter().map(|session| session.id).filter(|id| !paused.contains(id) && screencopy.is_powered(*id)).collect()
+}
+
 ///
-/// Returns an error if the output is not found, the capture session cannot be created or the texture cannot be set.
-///
-fn recreate_capture_session(session: &configuration::CaptureSession, screencopy: &mut screencopy::Screencopy, render_pipeline: &mut renderer::RenderPipeline) -> Result<(), anyhow::Error> {
-    // try to find the output by name, then by description
-    let output = screencopy.outputs.iter().find(
-        |output| output.1.name.as_deref().unwrap_or("") == session.output
-    ).or_else(|| {
-        screencopy.outputs.iter().find(
-            |output| session.output.contains(output.1.description.as_deref().unwrap_or(""))
-        )
-    }).context("output not found")?;
-
-    // create the capture session
-    let capture_session = screencopy::CaptureSession::new(
-        output.0.clone(),
-        session.region.left,
-        session.region.top,
-        session.region.width,
-        session.region.height
-    );
-    let bo = screencopy.set_capture_session(session.id, capture_session).context("failed to create capture session")?;
+/// Advances the fade between the rendered strips and the idle color by one frame.
+///
+/// # Arguments
+///
+/// * `level` - How far the strips are faded to the idle color, from 0 to 1.
+/// * `idle` - Whether the user is idle.
+/// * `frame_time` - The duration of a frame.
+/// * `config` - The configuration.
+///
+fn fade_idle(level: f32, idle: bool, frame_time: Duration, config: &configuration::Configuration) -> f32 {
+    let Some(idle_config) = &config.screencopy.idle else {
+        return 0.0;
+    };
 
-    // set the render texture
-    render_pipeline.set_texture(session.id, bo).context("failed to set texture")?;
+    let step = frame_time.as_secs_f32() / Duration::from_millis(idle_config.fade).as_secs_f32();
+    if idle { (level + step).min(1.0) } else { (level - step).max(0.0) }
+}
 
-    Ok(())
+///
+/// Blends a strip towards a color.
+///
+/// # Arguments
+///
+/// * `pixels` - The pixels of the strip.
+/// * `color` - The color to blend towards.
+/// * `level` - How far to blend, from 0 to 1.
+///
+fn blend(pixels: &mut [u8], color: &[u8; 3], level: f32) {
+    for pixel in pixels.chunks_exact_mut(3) {
+        for (channel, target) in pixel.iter_mut().zip(color) {
+            *channel = (*channel as f32 + (*target as f32 - *channel as f32) * level).round() as u8;
+        }
+    }
 }
 
 ///
-/// (Re)creates a device.
+/// Creates a device.
+///
+/// The device is connected once it is added to the connector, which keeps retrying in the background if that fails.
+///
+/// # Arguments
+///
+/// * `device` - The configuration of the device.
+///
+fn create_device(device: &configuration::Device) -> connector::device::Device {
+    let config = device.clone();
+    connector::device::Device::new(
+        Arc::new(move || create_sink(&config).context("failed to create device sink")),
+        device.physical_strips.iter().map(|strip| strip.leds).collect()
+    )
+}
+
+///
+/// Creates the sink of a device.
 ///
 /// # Arguments
 ///
 /// * `device` - The configuration of the device.
-/// * `connector` - The connector instance.
 ///
 /// # Errors
 ///
-/// Returns an error if the device cannot be created.
+/// Returns an error if the sink cannot be opened.
 ///
-fn recreate_devices(device: &configuration::Device, connector: &mut connector::Connector) -> Result<(), anyhow::Error> {
-    connector.set_device(
-        device.id,
-        connector::device::Device::new(
-            &PathBuf::from(device.port.clone()),
-            device.baud_rate,
-            device.physical_strips.iter().map(|strip| strip.leds).collect()
-        ).context("failed to create device")?
-    );
-
-    Ok(())
+fn create_sink(device: &configuration::Device) -> Result<Box<dyn connector::sink::Sink>, anyhow::Error> {
+    let leds = device.physical_strips.iter().map(|strip| strip.leds as usize).sum::<usize>();
+    let universes = leds.div_ceil(connector::e131::LEDS_PER_UNIVERSE) as u16;
+    Ok(match &device.sink {
+        configuration::Sink::Serial { port, baud_rate, protocol } =>
+            Box::new(connector::serial::Serial::new(&PathBuf::from(port), *baud_rate, match protocol {
+                configuration::SerialProtocol::Raw => connector::serial::Protocol::Raw,
+                configuration::SerialProtocol::Adalight => connector::serial::Protocol::Adalight
+            })?),
+        configuration::Sink::Wled { address, port, timeout } =>
+            Box::new(connector::wled::Wled::new(address, *port, *timeout)?),
+        configuration::Sink::Sacn { address, universe, priority, source_name } =>
+            Box::new(connector::e131::E131::new(address.as_deref(), *universe, universes, *priority, source_name)?),
+        configuration::Sink::Artnet { address, universe } =>
+            Box::new(connector::artnet::ArtNet::new(address, *universe, universes)?)
+    })
 }
 
 ///
-/// (Re)creates a program.
+/// Creates a program.
 ///
 /// # Arguments
 ///
+/// * `config_dir` - The directory shaders are resolved against.
 /// * `program` - The configuration of the program.
 /// * `config` - The configuration.
+/// * `render_pipeline` - The render pipeline instance.
 ///
 /// # Errors
 ///
 /// Returns an error if the program cannot be created.
 ///
-fn recreate_program(config_dir: &PathBuf, program: &configuration::Program, config: &configuration::Configuration, render_pipeline: &mut renderer::RenderPipeline) -> Result<(), anyhow::Error> {
+fn create_program(config_dir: &Path, program: &configuration::Program, config: &configuration::Configuration, render_pipeline: &renderer::RenderPipeline) -> Result<renderer::Program, anyhow::Error> {
     let vertex_shader = config_dir.join(&program.vertex_shader);
     let fragment_shader = config_dir.join(&program.fragment_shader);
-    render_pipeline.set_shader(
-        program.id,
+    let render_program = render_pipeline.create_shader(
         &program.capture_sessions,
         config.connector.strips.iter().find(|strip| strip.id == program.strip_id).context("strip not found")?.leds as u32, 1,
         &vertex_shader,
         &fragment_shader
-    ).context("failed to set shader")?;
+    ).context("failed to create shader")?;
 
-    Ok(())
+    // sessions cut from a shared capture are sampled from the texture of the entire output, which only works if the shader crops it
+    if config.screencopy.share_captures {
+        let cut = |id: &u64| config.screencopy.capture_sessions.iter().any(|session| session.id == *id && session.output.is_some());
+        if let Some((i, id)) = program.capture_sessions.iter().enumerate().find(|(i, id)| cut(id) && !render_program.samples_region(*i)) {
+            return Err(anyhow!("shaders do not declare the uniform texture{}Rect, which is required to sample capture session {} with share_captures", i, id));
+        }
+    }
+
+    Ok(render_program)
 }
 
 ///
-/// (Re)creates a strip.
+/// Creates a strip.
 ///
 /// # Arguments
 ///
 /// * `strip` - The configuration of the strip.
+///
+fn create_strip(strip: &configuration::Strip) -> connector::strip::Strip {
+    let mut connector_strip = connector::strip::Strip::new(strip.leds);
+    for mapping in &strip.mappings {
+        info!("mapping physical strip {} on device {} at offset {} with length {}{}", mapping.physical_strip_idx, mapping.device_id, mapping.offset, mapping.length, if mapping.reverse { " reversed" } else { "" });
+        let mut targets = vec![connector::strip::Target::new(mapping.device_id, mapping.physical_strip_idx, mapping.offset, mapping.reverse)];
+        for mirror in &mapping.mirrors {
+            info!("mirroring to physical strip {} on device {} at offset {}{}", mirror.physical_strip_idx, mirror.device_id, mirror.offset, if mirror.reverse { " reversed" } else { "" });
+            targets.push(connector::strip::Target::new(mirror.device_id, mirror.physical_strip_idx, mirror.offset, mirror.reverse));
+        }
+        connector_strip.map(connector::strip::Mapping::new(targets, mapping.length));
+    }
+    connector_strip
+}
+
+///
+/// Objects built from a configuration that have not been swapped in yet.
+///
+struct Staged {
+    devices: Vec<(u64, connector::device::Device)>,
+    strips: Vec<(u64, connector::strip::Strip)>,
+    sessions: Vec<(u64, Capture)>,
+    shared: SharedSessions, // shared sessions the sessions are cut from
+    paused: Vec<u64>, // sessions whose output is not plugged in
+    programs: Vec<(u64, renderer::Program)>
+}
+
+///
+/// Finds the entries of a configuration list that are new or differ from the previous configuration.
+///
+/// # Arguments
+///
+/// * `current` - The entries of the new configuration.
+/// * `previous` - The entries of the previous configuration, if any.
+///
+fn changed<'a, T: PartialEq>(current: &'a [T], previous: Option<&[T]>) -> Vec<&'a T> {
+    current.iter().filter(|item| !previous.is_some_and(|previous| previous.contains(item))).collect()
+}
+
+///
+/// Finds the ids of the previous configuration that are no longer present.
+///
+/// # Arguments
+///
+/// * `current` - The entries of the new configuration.
+/// * `previous` - The entries of the previous configuration.
+/// * `id` - Returns the id of an entry.
+///
+fn removed<T>(current: &[T], previous: &[T], id: fn(&T) -> u64) -> Vec<u64> {
+    previous.iter().map(id).filter(|old| !current.iter().any(|item| id(item) == *old)).collect()
+}
+
+///
+/// Finds the strips whose leds are blank after a reload: the recreated strips and every strip mapped or mirrored to a recreated device.
+///
+/// # Arguments
+///
+/// * `strips` - The strips of the configuration.
+/// * `strip_ids` - The ids of the recreated strips.
+/// * `device_ids` - The ids of the recreated devices.
+///
+fn reset_strips(strips: &[configuration::Strip], strip_ids: &HashSet<u64>, device_ids: &HashSet<u64>) -> HashSet<u64> {
+    let on_device = |strip: &configuration::Strip| strip.mappings.iter().any(|mapping|
+        device_ids.contains(&mapping.device_id) || mapping.mirrors.iter().any(|mirror| device_ids.contains(&mirror.device_id))
+    );
+    strips.iter().filter(|strip| strip_ids.contains(&strip.id) || on_device(strip)).map(|strip| strip.id).collect()
+}
+
+///
+/// Checks whether a file was modified since a point in time, assuming it was if that cannot be determined.
+///
+/// # Arguments
+///
+/// * `path` - The file to check.
+/// * `since` - The point in time.
+///
+fn modified_since(path: &Path, since: SystemTime) -> bool {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).map(|modified| modified >= since).unwrap_or(true)
+}
+
+///
+/// Builds the objects of a configuration that are new or changed, without touching the running constructs.
+///
+/// Dropping the result discards everything that was built, so a failed build leaves the previous configuration running.
+///
+/// # Arguments
+///
+/// * `config` - The configuration to build.
+/// * `previous` - The configuration currently running, `None` to build everything.
+/// * `since` - When the running configuration was built, programs with shaders modified after that are rebuilt.
+/// * `config_dir` - The directory shaders are resolved against.
+/// * `screencopy` - The screencopy instance.
+/// * `render_pipeline` - The render pipeline instance.
+///
+/// # Errors
+///
+/// Returns an error if any capture session or program cannot be created.
+///
+fn stage(config: &configuration::Configuration, previous: Option<&configuration::Configuration>, since: SystemTime, config_dir: &Path, screencopy: &screencopy::Screencopy, render_pipeline: &renderer::RenderPipeline) -> Result<Staged, anyhow::Error> {
+    let mut staged = Staged { devices: Vec::new(), strips: Vec::new(), sessions: Vec::new(), shared: Vec::new(), paused: Vec::new(), programs: Vec::new() };
+
+    // create devices
+    info!("creating devices");
+    for device in changed(&config.connector.devices, previous.map(|previous| previous.connector.devices.as_slice())) {
+        info!("creating device {} on {} with {} physical strips", device.id, device.sink, device.physical_strips.len());
+        staged.devices.push((device.id, create_device(device)));
+    }
+
+    // create strips
+    info!("creating strips");
+    for strip in changed(&config.connector.strips, previous.map(|previous| previous.connector.strips.as_slice())) {
+        info!("creating strip {} with {} leds consisting of {} mapping(s)", strip.id, strip.leds, strip.mappings.len());
+        staged.strips.push((strip.id, create_strip(strip)));
+    }
+
+    // create capture sessions, all of them if sharing captures was toggled
+    info!("creating capture sessions");
+    let share = config.screencopy.share_captures;
+    let previous_sessions = previous.filter(|previous| previous.screencopy.share_captures == share).map(|previous| previous.screencopy.capture_sessions.as_slice());
+    for session in changed(&config.screencopy.capture_sessions, previous_sessions) {
+        if !is_available(session, screencopy) {
+            warn!("{} of capture session {} is not plugged in, pausing", target(session), session.id);
+            staged.paused.push(session.id);
+            continue;
+        }
+
+        info!("creating capture session {} for {} at {}", session.id, target(session), session.region);
+        let capture = create_capture_session(session, share, &mut staged.shared, screencopy, render_pipeline)
+            .with_context(|| format!("failed to create capture session {}", session.id))?;
+        staged.sessions.push((session.id, capture));
+    }
+
+    // create programs, which also depend on the size of their strip and the contents of their shaders
+    info!("creating programs");
+    let leds = |config: &configuration::Configuration, id: u64| config.connector.strips.iter().find(|strip| strip.id == id).map(|strip| strip.leds);
+    for program in &config.render_pipeline.programs {
+        let unchanged = previous.is_some_and(|previous|
+            previous.render_pipeline.programs.contains(program)
+                && previous.screencopy.share_captures == config.screencopy.share_captures
+                && leds(previous, program.strip_id) == leds(config, program.strip_id)
+                && !modified_since(&config_dir.join(&program.vertex_shader), since)
+                && !modified_since(&config_dir.join(&program.fragment_shader), since)
+        );
+        if unchanged {
+            debug!("keeping program {}", program.id);
+            continue;
+        }
+
+        info!("creating program {} with {} textures from {} and {} to {}", program.id, program.capture_sessions.len(), program.vertex_shader, program.fragment_shader, program.strip_id);
+        let render_program = create_program(config_dir, program, config, render_pipeline)
+            .with_context(|| format!("failed to create program {}", program.id))?;
+        staged.programs.push((program.id, render_program));
+    }
+
+    Ok(staged)
+}
+
+///
+/// Swaps the staged objects into the running constructs and removes whatever the new configuration no longer contains.
+///
+/// # Arguments
+///
+/// * `staged` - The staged objects.
+/// * `config` - The configuration the objects were built from.
+/// * `previous` - The configuration currently running, if any.
+/// * `paused` - The paused capture sessions.
+/// * `screencopy` - The screencopy instance.
 /// * `connector` - The connector instance.
+/// * `render_pipeline` - The render pipeline instance.
+///
+fn commit(staged: Staged, config: &configuration::Configuration, previous: Option<&configuration::Configuration>, paused: &mut HashSet<u64>, screencopy: &mut screencopy::Screencopy, connector: &mut connector::Connector, render_pipeline: &mut renderer::RenderPipeline) {
+    if let Some(previous) = previous {
+        for id in removed(&config.connector.devices, &previous.connector.devices, |device| device.id) {
+            info!("removing device {}", id);
+            connector.remove_device(id);
+        }
+        for id in removed(&config.connector.strips, &previous.connector.strips, |strip| strip.id) {
+            info!("removing strip {}", id);
+            connector.remove_strip(id);
+        }
+        for id in removed(&config.screencopy.capture_sessions, &previous.screencopy.capture_sessions, |session| session.id) {
+            info!("removing capture session {}", id);
+            screencopy.remove_capture_session(id);
+            render_pipeline.remove_texture(id);
+            paused.remove(&id);
+        }
+        for id in removed(&config.render_pipeline.programs, &previous.render_pipeline.programs, |program| program.id) {
+            info!("removing program {}", id);
+            render_pipeline.remove_shader(id);
+        }
+    }
+
+    connector.set_backoff(create_backoff(config));
+    let idle_timeout = |config: &configuration::Configuration| config.screencopy.idle.as_ref().map(|idle| Duration::from_secs(idle.timeout));
+    if previous.is_none_or(|previous| idle_timeout(previous) != idle_timeout(config)) {
+        screencopy.set_idle_timeout(idle_timeout(config));
+    }
+    connector.set_keepalive(Duration::from_millis(config.connector.keepalive));
+    render_pipeline.set_background(config.screencopy.fallback_color);
+    let mut devices = HashSet::new();
+    for (id, device) in staged.devices {
+        connector.set_device(id, device);
+        devices.insert(id);
+    }
+    let mut strips = HashSet::new();
+    for (id, strip) in staged.strips {
+        connector.set_strip(id, strip);
+        strips.insert(id);
+    }
+    let reset_strips = reset_strips(&config.connector.strips, &strips, &devices);
+    for (source, session, texture) in staged.shared {
+        screencopy.set_capture_session(source, session);
+        render_pipeline.set_texture(source, texture);
+    }
+    for (id, capture) in staged.sessions {
+        set_capture_session(id, capture, Vec::new(), screencopy, render_pipeline);
+        paused.remove(&id);
+    }
+    for id in staged.paused {
+        pause_capture_session(id, paused, screencopy, render_pipeline);
+    }
+    for (id, program) in staged.programs {
+        render_pipeline.set_shader(id, program);
+    }
+
+    // programs rendering to new strips or strips on new devices have to fill them even if nothing changed on screen
+    for program in config.render_pipeline.programs.iter().filter(|program| reset_strips.contains(&program.strip_id)) {
+        render_pipeline.invalidate(program.id);
+    }
+}
+
+///
+/// Creates the reconnect backoff of the connector.
+///
+/// # Arguments
+///
+/// * `config` - The configuration.
+///
+fn create_backoff(config: &configuration::Configuration) -> connector::Backoff {
+    connector::Backoff {
+        initial: Duration::from_millis(config.connector.reconnect.initial_delay),
+        max: Duration::from_millis(config.connector.reconnect.max_delay)
+    }
+}
+
+///
+/// Collects the files whose changes trigger a reload: the configuration file and every shader it references.
+///
+/// Files that are symlinks are watched as well as their targets, so both replacing the link and editing the target trigger a reload.
+///
+/// # Arguments
+///
+/// * `config_file` - The configuration file.
+/// * `config` - The configuration.
+/// * `config_dir` - The directory shaders are resolved against.
+///
+fn collect_watched_files(config_file: &Path, config: &configuration::Configuration, config_dir: &Path) -> HashSet<PathBuf> {
+    let shaders = config.render_pipeline.programs.iter()
+        .flat_map(|program| [&program.vertex_shader, &program.fragment_shader])
+        .map(|shader| config_dir.join(shader));
+    std::iter::once(config_file.to_path_buf()).chain(shaders)
+        .flat_map(|file| [std::fs::canonicalize(&file).ok(), Some(std::path::absolute(&file).unwrap_or(file))])
+        .flatten()
+        .collect()
+}
+
+///
+/// Points the watcher at the directories containing the watched files.
+///
+/// Directories are watched instead of the files themselves, so editors that save by renaming a new file over the old one are still noticed.
+///
+/// # Arguments
+///
+/// * `watcher` - The file watcher.
+/// * `watched_dirs` - The directories currently watched, updated in place.
+/// * `files` - The files to watch.
+///
+fn rewatch(watcher: &mut RecommendedWatcher, watched_dirs: &mut HashSet<PathBuf>, files: &HashSet<PathBuf>) {
+    let dirs = files.iter().filter_map(|file| file.parent().map(Path::to_path_buf)).collect::<HashSet<PathBuf>>();
+    for dir in watched_dirs.difference(&dirs) {
+        debug!("unwatching {}", dir.display());
+        let _ = watcher.unwatch(dir);
+    }
+    for dir in dirs.difference(watched_dirs) {
+        debug!("watching {}", dir.display());
+        if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+            warn!("failed to watch {}: {:?}", dir.display(), e);
+        }
+    }
+    *watched_dirs = dirs;
+}
+
+///
+/// Checks whether a file event may have changed the contents of a watched file.
+///
+/// # Arguments
+///
+/// * `event` - The file event.
+/// * `files` - The watched files.
+///
+fn is_relevant(event: &Event, files: &HashSet<PathBuf>) -> bool {
+    let kind = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) && !matches!(event.kind, EventKind::Modify(ModifyKind::Metadata(_)));
+    kind && event.paths.iter().any(|path| files.contains(path))
+}
+
+///
+/// Resolves the configuration file.
+///
+/// # Arguments
+///
+/// * `config` - The configuration file specified on the command line.
 ///
 /// # Errors
 ///
-/// Returns an error if the strip cannot be created.
+/// Returns an error if the configuration file does not exist.
 ///
-fn recreate_strip(strip: &configuration::Strip, connector: &mut connector::Connector) {
-    let mut connector_strip = connector::strip::Strip::new(strip.leds);
-    for mapping in &strip.mappings {
-        info!("mapping physical strip {} on device {} at offset {} with length {}", mapping.physical_strip_idx, mapping.device_id, mapping.offset, mapping.length);
-        connector_strip.map(connector::strip::Mapping::new(mapping.device_id, mapping.physical_strip_idx, mapping.offset, mapping.length));
+fn resolve_config_file(config: Option<&PathBuf>) -> Result<PathBuf, anyhow::Error> {
+    let config_file = config.cloned().unwrap_or_else(|| dirs::config_dir().unwrap().join("ambient-led").join("config.yml"));
+    if !config_file.exists() {
+        return Err(anyhow!("specified configuration file does not exist"));
+    }
+
+    Ok(config_file)
+}
+
+///
+/// Parses and validates the configuration file.
+///
+/// # Arguments
+///
+/// * `config_file` - The configuration file to load.
+///
+/// # Errors
+///
+/// Returns an error if the configuration file cannot be parsed or is invalid, listing every problem found.
+///
+fn load_configuration(config_file: &PathBuf) -> Result<configuration::Configuration, anyhow::Error> {
+    let config = configuration::Configuration::new(config_file).context("failed to parse configuration file")?;
+    let problems = config.validate();
+    if !problems.is_empty() {
+        let list = problems.iter().map(|problem| format!("\n  {}", problem)).collect::<String>();
+        return Err(anyhow!("configuration file has {} problem(s):{}", problems.len(), list));
     }
-    connector.set_strip(strip.id, connector_strip);
+
+    Ok(config)
+}
+
+///
+/// Checks the configuration file without opening any devices or outputs.
+///
+/// # Arguments
+///
+/// * `config` - The configuration file to check.
+///
+/// # Errors
+///
+/// Returns an error if the configuration file does not exist, cannot be parsed or is invalid.
+///
+pub fn check(config: Option<&PathBuf>) -> Result<(), anyhow::Error> {
+    let config_file = resolve_config_file(config)?;
+    load_configuration(&config_file)?;
+    println!("{}: configuration is valid", config_file.display());
+
+    Ok(())
 }
 
 ///
@@ -135,19 +1129,10 @@ fn recreate_strip(strip: &configuration::Strip, connector: &mut connector::Conne
 pub fn init(verbose: bool, frames: Option<&u32>, config: Option<&PathBuf>) -> Result<(), anyhow::Error> {
     // find .config directory
     let config_dir = dirs::config_dir().unwrap().join("ambient-led");
-    let default_config = config_dir.join("config.yml");
-    let config_file = config.unwrap_or(&default_config);
-    if !config_file.exists() {
-        return Err(anyhow!("specified configuration file does not exist"));
-    }
-
-    // create file change watcher
-    let (tx, rx) = channel();
-    let mut watcher = RecommendedWatcher::new(tx, Config::default())?;
-    watcher.watch(&config_dir, RecursiveMode::Recursive)?;
+    let config_file = &resolve_config_file(config)?;
 
     // parse the configuration file  initially
-    let config = configuration::Configuration::new(config_file).context("failed to parse configuration file")?;
+    let mut config = load_configuration(config_file)?;
 
     // initialize the logger
     let level = if verbose { LevelFilter::Trace } else { log::LevelFilter::from_str(&config.log_level).context("invalid log level")? };
@@ -157,108 +1142,308 @@ pub fn init(verbose: bool, frames: Option<&u32>, config: Option<&PathBuf>) -> Re
 
     // initialize constructs
     let mut screencopy = screencopy::Screencopy::new(config.screencopy.gbm_device.clone())?;
-    let mut connector = connector::Connector::new();
+    let mut connector = connector::Connector::new(create_backoff(&config), Duration::from_millis(config.connector.keepalive));
     let mut render_pipeline = renderer::RenderPipeline::new(screencopy.get_display_id())?;
 
-    loop {
-        // parse the configuration file
-        let config = configuration::Configuration::new(config_file).context("failed to parse configuration file")?;
-
-        // reset the constructs
-        screencopy.reset();
-        connector.reset();
-        render_pipeline.reset();
-
-        // add devices
-        info!("adding devices to connector");
-        for device in &config.connector.devices {
-            info!("adding device {} on {} @{}Bd with {} physical strips", device.id, device.port, device.baud_rate, device.physical_strips.len());
-            recreate_devices(device, &mut connector).context("failed to create device, panicking")?;
-        }
+    // build the initial configuration
+    let mut built_at = SystemTime::now();
+    let staged = stage(&config, None, built_at, &config_dir, &screencopy, &render_pipeline).context("failed to apply configuration")?;
+    let mut paused = HashSet::new();
+    commit(staged, &config, None, &mut paused, &mut screencopy, &mut connector, &mut render_pipeline);
 
-        // add strips
-        info!("adding strips to connector");
-        for strip in &config.connector.strips {
-            info!("adding strip {} with {} leds consisting of {} mapping(s)", strip.id, strip.leds, strip.mappings.len());
-            recreate_strip(strip, &mut connector);
-        }
+    // watch the configuration file and shaders for changes
+    let (tx, rx) = channel();
+    let mut watcher = RecommendedWatcher::new(tx, Config::default())?;
+    let mut watched_files = collect_watched_files(config_file, &config, &config_dir);
+    let mut watched_dirs = HashSet::new();
+    rewatch(&mut watcher, &mut watched_dirs, &watched_files);
+    let mut last_change = None;
 
-        // add capture sessions
-        info!("creating capture sessions");
-        for session in &config.screencopy.capture_sessions {
-            info!("creating capture session {} for output {} at {}, {} with size {}x{}", session.id, session.output, session.region.left, session.region.top, session.region.width, session.region.height);
-            recreate_capture_session(session, &mut screencopy, &mut render_pipeline).context("failed to recreate capture session, panicking")?;
-        }
+    // prepare optional frame limit
+    let mut captured_frames = 0;
 
-        // add programs
-        info!("adding programs to render pipeline");
-        for program in &config.render_pipeline.programs {
-            info!("adding program {} with {} textures from {} and {} to {}", program.id, program.capture_sessions.len(), program.vertex_shader, program.fragment_shader, program.strip_id);
-            recreate_program(&config_dir, program, &config, &mut render_pipeline).context("failed to recreate program, panicking")?;
-        }
+    // fade to the idle color while the user is idle
+    let mut idle = false;
+    let mut idle_level = 0.0;
+
+    // when the sessions whose capture failed are recreated
+    let mut capture_retries = HashMap::new();
 
-        // prepare optional frame limit
-        let mut captured_frames = 0;
+    // programs that are dark because all of their outputs are powered off
+    let mut dark = HashSet::new();
 
-        // start the render loop
+    // start the render loop
+    info!("starting render loop with {} fps", config.fps);
+    screencopy.request_frames(active_sessions(&config, &paused, &screencopy))?;
+    loop {
+        let start = std::time::Instant::now();
         let frame_time = std::time::Duration::from_secs_f32(1.0 / (config.fps as f32));
-        info!("starting render loop with {} fps", config.fps);
-        loop {
-            let start = std::time::Instant::now();
 
-            // capture the screens
+        // wait for the frames requested during the previous frame, frames of unchanged screens stay pending
+        screencopy.wait_for_frames(frame_time)?;
+
+        // follow outputs being plugged in and out
+        if screencopy.poll_outputs()? {
+            update_sessions(&config, &mut paused, &mut screencopy, &mut render_pipeline);
+        }
+        let was_idle = std::mem::replace(&mut idle, screencopy.is_idle());
+        let previous_idle_level = idle_level;
+        idle_level = fade_idle(idle_level, idle, frame_time, &config);
+
+        // once the user is back, capture fresh frames before fading back in instead of showing the ones from before,
+        // recreating the sessions whose frames failed in the meantime, e.g. while the screen was locked
+        if was_idle && !idle {
             for session in &config.screencopy.capture_sessions {
-                let status = screencopy.capture(session.id);
-                if status.is_err() { // capture session will occasionally fail when there's a lot of lag or monitors are being added/removed
-                    warn!("failed to capture session {}: {:?}", session.id, status);
-                    std::thread::sleep(std::time::Duration::from_secs(2)); // serial timeout is 5 seconds, so waiting here is fine in case the compositor is frozen
-                    recreate_capture_session(session, &mut screencopy, &mut render_pipeline).context("failed to recreate capture session, panicking")?;
-                    info!("recreated capture session {}", session.id);
+                if paused.contains(&session.id) || screencopy.frame_status(session.id).is_ok() {
+                    continue;
+                }
+                match recreate_capture_session(session, config.screencopy.share_captures, &mut screencopy, &mut render_pipeline) {
+                    Ok(()) => info!("recreated capture session {}, which failed while the user was idle", session.id),
+                    Err(e) => warn!("failed to recreate capture session {} after the user was idle: {:?}", session.id, e)
                 }
             }
+            screencopy.request_frames(active_sessions(&config, &paused, &screencopy))?;
+            screencopy.wait_for_frames(frame_time)?;
+        }
 
-            // render the strips
-            for program in &config.render_pipeline.programs {
-                render_pipeline.render(program.id, connector.mutate_strip(program.strip_id).unwrap());
+        // check the captured screens (captures are stopped while idle)
+        for session in &config.screencopy.capture_sessions {
+            if idle || paused.contains(&session.id) || !screencopy.is_powered(session.id) {
+                continue;
             }
 
-            // send the data to the devices
-            let status = connector.write();
-            if status.is_err() { // serial port seems to fail occasionally, not good, but we can recover
-                warn!("failed to write to devices: {:?}", status);
-                std::thread::sleep(std::time::Duration::from_secs(2));
-                for device in &config.connector.devices {
-                    recreate_devices(device, &mut connector).context("failed to recreate device, panicking")?;
+            if screencopy.is_outdated(session.id) {
+                match recreate_capture_session(session, config.screencopy.share_captures, &mut screencopy, &mut render_pipeline) {
+                    Ok(()) => info!("recreated capture session {} for the new buffer constraints", session.id),
+                    Err(e) => {
+                        warn!("failed to recreate outdated capture session {}, pausing: {:?}", session.id, e);
+                        pause_capture_session(session.id, &mut paused, &mut screencopy, &mut render_pipeline);
+                    }
                 }
-                info!("recreated devices");
+                continue;
             }
 
-            let elapsed = start.elapsed();
-            if elapsed < frame_time {
-                std::thread::sleep(frame_time - elapsed);
+            let status = screencopy.frame_status(session.id);
+            if status.is_err() { // capture session will occasionally fail when there's a lot of lag or monitors are being added/removed
+                // give a frozen compositor a moment before recreating the session, while the other strips keep going
+                let now = Instant::now();
+                let retry_at = *capture_retries.entry(session.id).or_insert_with(|| {
+                    warn!("failed to capture session {}, retrying in {:?}: {:?}", session.id, CAPTURE_RETRY_DELAY, status);
+                    now + CAPTURE_RETRY_DELAY
+                });
+                if now < retry_at {
+                    continue;
+                }
+                capture_retries.remove(&session.id);
+
+                screencopy.poll_outputs()?;
+                if !is_available(session, &screencopy) {
+                    warn!("{} of capture session {} is gone, pausing", target(session), session.id);
+                    pause_capture_session(session.id, &mut paused, &mut screencopy, &mut render_pipeline);
+                    continue;
+                }
+                match recreate_capture_session(session, config.screencopy.share_captures, &mut screencopy, &mut render_pipeline) {
+                    Ok(()) => info!("recreated capture session {}", session.id),
+                    Err(e) => {
+                        warn!("failed to recreate capture session {}, pausing: {:?}", session.id, e);
+                        pause_capture_session(session.id, &mut paused, &mut screencopy, &mut render_pipeline);
+                    }
+                }
+            } else {
+                capture_retries.remove(&session.id);
             }
+        }
+        render_pipeline.update_sources(&screencopy).context("failed to update captured textures")?;
 
-            // check if the frame limit is reached
-            if let Some(frames) = frames {
-                captured_frames += 1;
-                if captured_frames >= *frames {
-                    info!("captured {} frames, exiting", frames);
-                    return Ok(());
+        // render the strips whose regions changed, or every strip while fading
+        let fading = idle_level > 0.0 || previous_idle_level > 0.0;
+        let idle_color = config.screencopy.idle.as_ref().map_or([0; 3], |idle| idle.color);
+        for program in &config.render_pipeline.programs {
+            if idle_level >= 1.0 {
+                if previous_idle_level < 1.0 {
+                    blend(connector.mutate_strip(program.strip_id).unwrap(), &idle_color, 1.0);
                 }
+                continue;
             }
 
-            // check for configuration changes
-            let mut changed = false;
-            for event in rx.try_iter() {
-                if event.is_ok() && event.as_ref().unwrap().kind == EventKind::Modify(ModifyKind::Data(DataChange::Any)) {
-                    changed = true;
+            let powered_off = !program.capture_sessions.is_empty() && program.capture_sessions.iter().all(|id| !screencopy.is_powered(*id));
+            let woke = !powered_off && dark.remove(&program.id);
+            let pixels = if powered_off {
+                if !dark.insert(program.id) && !fading {
+                    continue;
+                }
+                let pixels = connector.mutate_strip(program.strip_id).unwrap();
+                pixels.fill(0);
+                pixels
+            } else if program.capture_sessions.iter().any(|id| paused.contains(id)) {
+                let pixels = connector.mutate_strip(program.strip_id).unwrap();
+                for pixel in pixels.chunks_exact_mut(3) {
+                    pixel.copy_from_slice(&config.screencopy.fallback_color);
                 }
+                pixels
+            } else if fading || woke || render_pipeline.needs_render(program.id) || program.capture_sessions.iter().any(|id| screencopy.is_damaged(*id)) {
+                let pixels = connector.mutate_strip(program.strip_id).unwrap();
+                render_pipeline.render(program.id, pixels);
+                pixels
+            } else {
+                continue;
+            };
+            if idle_level > 0.0 {
+                blend(pixels, &idle_color, idle_level);
+            }
+        }
+
+        // request the next frames, so they are copied while the strips are written and the loop sleeps
+        if !idle {
+            screencopy.request_frames(active_sessions(&config, &paused, &screencopy))?;
+        }
+
+        // send the changed strips to the devices (failed devices are reconnected by the connector)
+        connector.write().context("failed to write strips to devices")?;
+
+        let elapsed = start.elapsed();
+        if elapsed < frame_time {
+            std::thread::sleep(frame_time - elapsed);
+        }
+
+        // check if the frame limit is reached
+        if let Some(frames) = frames {
+            captured_frames += 1;
+            if captured_frames >= *frames {
+                info!("captured {} frames, exiting", frames);
+                return Ok(());
             }
-            if changed {
-                info!("configuration file changed, reloading");
-                break;
+        }
+
+        // check for configuration changes, waiting for the files to settle before reloading
+        for event in rx.try_iter() {
+            match event {
+                Ok(event) if is_relevant(&event, &watched_files) => last_change = Some(Instant::now()),
+                Ok(_) => (),
+                Err(e) => warn!("file watcher error: {:?}", e)
             }
         }
+        if last_change.is_none_or(|last_change: Instant| last_change.elapsed() < RELOAD_DEBOUNCE) {
+            continue;
+        }
+        last_change = None;
+
+        // reload the configuration, keeping the previous one running if anything fails
+        info!("configuration file changed, reloading");
+        let reload_started = SystemTime::now();
+        let reloaded = load_configuration(config_file).and_then(|new_config| {
+            let staged = stage(&new_config, Some(&config), built_at, &config_dir, &screencopy, &render_pipeline)?;
+            Ok((new_config, staged))
+        });
+        match reloaded {
+            Ok((new_config, staged)) => {
+                commit(staged, &new_config, Some(&config), &mut paused, &mut screencopy, &mut connector, &mut render_pipeline);
+                config = new_config;
+                built_at = reload_started;
+                watched_files = collect_watched_files(config_file, &config, &config_dir);
+                rewatch(&mut watcher, &mut watched_dirs, &watched_files);
+                info!("reloaded configuration, running with {} fps", config.fps);
+            },
+            Err(e) => warn!("failed to reload configuration, keeping the previous configuration: {:?}", e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{changed, collect_watched_files, configuration::Configuration, matches_pattern, reset_strips};
+
+    const CONFIG: &str = "
+connector:
+  devices:
+    - id: 1
+      port: /dev/ttyUSB0
+      baud_rate: 500000
+      physical_strips:
+        - leds: 20
+    - id: 2
+      port: /dev/ttyUSB1
+      baud_rate: 500000
+      physical_strips:
+        - leds: 40
+  strips:
+    - id: 1
+      leds: 20
+      mappings:
+        - device_id: 1
+          physical_strip_idx: 0
+          offset: 0
+          length: 20
+    - id: 2
+      leds: 20
+      mappings:
+        - device_id: 2
+          physical_strip_idx: 0
+          offset: 0
+          length: 20
+          mirrors:
+            - device_id: 1
+              physical_strip_idx: 0
+              offset: 0
+    - id: 3
+      leds: 20
+      mappings:
+        - device_id: 2
+          physical_strip_idx: 0
+          offset: 20
+          length: 20
+screencopy:
+  capture_sessions: []
+render_pipeline:
+  programs: []
+fps: 60
+log_level: info
+";
 
+    #[test]
+    fn match_patterns() {
+        assert!(matches_pattern("DP-1", "DP-1"));
+        assert!(!matches_pattern("DP-1", "DP-10"));
+        assert!(matches_pattern("DP-?", "DP-3"));
+        assert!(matches_pattern("Dell*", "Dell Inc. DELL U2720Q"));
+        assert!(matches_pattern("*U27*", "Dell Inc. DELL U2720Q"));
+        assert!(!matches_pattern("*U27", "Dell Inc. DELL U2720Q"));
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("*a", "*ba"));
+        assert!(matches_pattern("?a", "*a"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn reset_strips_of_recreated_devices() {
+        let previous: Configuration = serde_yml::from_str(CONFIG).unwrap();
+        let config: Configuration = serde_yml::from_str(&CONFIG.replacen("baud_rate: 500000", "baud_rate: 115200", 1)).unwrap();
+
+        // only device 1 is recreated, its strip and the strip mirrored to it have to be rendered again
+        let devices = changed(&config.connector.devices, Some(&previous.connector.devices)).iter().map(|device| device.id).collect::<HashSet<_>>();
+        let strips = changed(&config.connector.strips, Some(&previous.connector.strips)).iter().map(|strip| strip.id).collect::<HashSet<_>>();
+        assert_eq!(devices, HashSet::from([1]));
+        assert!(strips.is_empty());
+        assert_eq!(reset_strips(&config.connector.strips, &strips, &devices), HashSet::from([1, 2]));
+
+        // recreated strips are reset as well
+        assert_eq!(reset_strips(&config.connector.strips, &HashSet::from([3]), &HashSet::new()), HashSet::from([3]));
+    }
+
+    #[test]
+    fn watch_symlink_targets() {
+        let dir = std::env::temp_dir().join(format!("ambient-led-watch-{}", std::process::id()));
+        let (dotfiles, config_dir) = (dir.join("dotfiles"), dir.join("config"));
+        std::fs::create_dir_all(&dotfiles).unwrap();
+        std::fs::create_dir_all(&config_dir).unwrap();
+        std::fs::write(dotfiles.join("config.yml"), CONFIG).unwrap();
+        std::os::unix::fs::symlink(dotfiles.join("config.yml"), config_dir.join("config.yml")).unwrap();
+
+        let config: Configuration = serde_yml::from_str(CONFIG).unwrap();
+        let files = collect_watched_files(&config_dir.join("config.yml"), &config, &config_dir);
+        let dotfiles = std::fs::canonicalize(&dotfiles).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, HashSet::from([config_dir.join("config.yml"), dotfiles.join("config.yml")]));
+    }
+}
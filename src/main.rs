@@ -40,10 +40,21 @@ fn main() -> Result<(), anyhow::Error> {
                 .action(ArgAction::Set)
                 .num_args(1)
                 .value_parser(clap::value_parser!(PathBuf))
+                .global(true)
+        )
+        .subcommand(
+            Command::new("check")
+                .about("validate the configuration file and exit")
         );
 
-    // launch the application
+    // check the configuration if requested
     let matches = cmd.get_matches();
+    if matches.subcommand_matches("check").is_some() {
+        init::check(matches.get_one("config")).context("configuration check failed")?;
+        return Ok(());
+    }
+
+    // launch the application
     init::init(
         matches.get_flag("verbose"),
         matches.get_one("frames"),
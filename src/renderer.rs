@@ -7,7 +7,9 @@ use log::{debug, trace};
 use shaders::Shader;
 use textures::Texture;
 use vertices::VertexArrayObject;
-use wayland_client::backend::ObjectId;
+use wayland_client::{backend::ObjectId, protocol::wl_output::Transform};
+
+use crate::screencopy::{Screencopy, ShmBuffer};
 
 mod env;
 mod framebuffer;
@@ -15,6 +17,210 @@ mod shaders;
 mod textures;
 mod vertices;
 
+pub use textures::DRM_FORMAT_MOD_INVALID;
+
+///
+/// Shader program rendering into its own framebuffer
+///
+pub struct Program {
+    shader: Shader,
+    framebuffer: Framebuffer,
+    rendered: bool // whether the program was rendered since it was set or invalidated
+}
+
+impl Program {
+
+    ///
+    /// Check whether the program samples only the region of a texture cut from a shared texture, through its `textureNRect` uniform
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the texture
+    ///
+    pub fn samples_region(&self, index: usize) -> bool {
+        self.shader.has_rect_uniform(index)
+    }
+
+}
+
+/// Region of a texture covering all of it
+const FULL_RECT: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// Vertex shader turning the captured buffer upright, mapping texture coordinates through `uv_transform`
+const VIEW_VERTEX_SHADER: &str = r#"#version 330 core
+layout (location = 0) in vec3 Pos;
+layout (location = 1) in vec2 ImageTexCoord;
+uniform mat3 uv_transform;
+out vec2 TexCoord;
+void main() {
+    gl_Position = vec4(Pos, 1.0);
+    TexCoord = (uv_transform * vec3(ImageTexCoord, 1.0)).xy;
+}
+"#;
+
+/// Fragment shader copying the captured buffer
+const VIEW_FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec2 TexCoord;
+out vec4 FragColor;
+uniform sampler2D texture0;
+void main() {
+    FragColor = texture(texture0, TexCoord);
+}
+"#;
+
+///
+/// Screencopy texture, optionally cropped to a region of the captured buffer and turned upright
+///
+pub struct Source {
+    framebuffer: Framebuffer, // framebuffer around the dmabuf texture
+    view: Option<(Framebuffer, [f32; 9])>, // upright copy and the row-major matrix mapping its texture coordinates to the captured buffer
+    rows: (u32, u32) // first row and number of rows of the captured buffer that are sampled, uploaded from shared memory
+}
+
+impl Source {
+
+    ///
+    /// Copy the cropped region out of the captured buffer, undoing the transform of the output
+    ///
+    /// # Arguments
+    ///
+    /// * `shader` - The view shader
+    /// * `uv_transform` - The location of the `uv_transform` uniform
+    ///
+    fn update(&self, shader: &Shader, uv_transform: i32) {
+        if let Some((view, matrix)) = &self.view {
+            let textures = vec![&self.framebuffer.color];
+            view.bind();
+            shader.bind(&textures, &[FULL_RECT]);
+            unsafe {
+                gl::UniformMatrix3fv(uv_transform, 1, gl::TRUE, matrix.as_ptr());
+                gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
+            }
+            shader.unbind(&textures);
+            view.unbind();
+        }
+    }
+
+    ///
+    /// Upload the rows of a frame captured into shared memory that are sampled
+    ///
+    /// # Arguments
+    ///
+    /// * `shm` - The shared memory buffer of the capture
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pixels cannot be uploaded
+    ///
+    fn upload(&self, shm: &ShmBuffer) -> Result<(), anyhow::Error> {
+        let (first, height) = self.rows;
+        let pixels = shm.pixels().get(first as usize * shm.stride as usize..).context("pixel buffer is too small")?;
+        self.framebuffer.color.upload(pixels, self.framebuffer.width, (first, height), shm.stride, shm.format).map_err(|e| anyhow!(e))
+    }
+
+    ///
+    /// Get the texture shaders sample from
+    ///
+    fn texture(&self) -> &Texture {
+        self.view.as_ref().map(|(view, _)| &view.color).unwrap_or(&self.framebuffer.color)
+    }
+
+}
+
+///
+/// Screencopy texture composed of regions of shared textures, e.g. to span multiple outputs
+///
+struct Composite {
+    framebuffer: Framebuffer,
+    parts: Vec<(u64, [f32; 4], [f32; 4])> // id of the shared texture, region sampled from it and region of the composite it covers
+}
+
+impl Composite {
+
+    ///
+    /// Draw the regions of the shared textures into the composed texture
+    ///
+    /// # Arguments
+    ///
+    /// * `textures` - The screen textures, including the shared textures
+    /// * `background` - The color of the regions no part covers, e.g. gaps between outputs
+    /// * `shader` - The view shader
+    /// * `uv_transform` - The location of the `uv_transform` uniform
+    ///
+    fn update(&self, textures: &HashMap<u64, Source>, background: [f32; 3], shader: &Shader, uv_transform: i32) {
+        let parts = self.parts.iter()
+            .filter_map(|(tid, rect, dest)| textures.get(tid).map(|source| (source, rect, dest)))
+            .collect::<Vec<_>>();
+        for (source, _, _) in &parts {
+            source.update(shader, uv_transform);
+        }
+
+        let (width, height) = (self.framebuffer.width as f32, self.framebuffer.height as f32);
+        let [r, g, b] = background;
+        self.framebuffer.bind();
+        unsafe {
+            gl::ClearColor(r, g, b, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+        }
+        for (source, [x, y, w, h], [dx, dy, dw, dh]) in parts {
+            let textures = vec![source.texture()];
+            let matrix = [*w, 0.0, *x, 0.0, *h, *y, 0.0, 0.0, 1.0];
+            shader.bind(&textures, &[FULL_RECT]);
+            unsafe {
+                gl::Viewport((dx * width).round() as i32, (dy * height).round() as i32, (dw * width).round() as i32, (dh * height).round() as i32);
+                gl::UniformMatrix3fv(uv_transform, 1, gl::TRUE, matrix.as_ptr());
+                gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
+            }
+            shader.unbind(&textures);
+        }
+        self.framebuffer.unbind();
+    }
+
+}
+
+///
+/// Get the affine map from upright texture coordinates to texture coordinates of a buffer with the given transform
+///
+/// # Arguments
+///
+/// * `transform` - The transform of the buffer contents
+///
+fn buffer_coordinates(transform: Transform) -> [f32; 6] {
+    match transform {
+        Transform::_90 => [0.0, 1.0, 0.0, -1.0, 0.0, 1.0],
+        Transform::_180 => [-1.0, 0.0, 1.0, 0.0, -1.0, 1.0],
+        Transform::_270 => [0.0, -1.0, 1.0, 1.0, 0.0, 0.0],
+        Transform::Flipped => [-1.0, 0.0, 1.0, 0.0, 1.0, 0.0],
+        Transform::Flipped90 => [0.0, 1.0, 0.0, 1.0, 0.0, 0.0],
+        Transform::Flipped180 => [1.0, 0.0, 0.0, 0.0, -1.0, 1.0],
+        Transform::Flipped270 => [0.0, -1.0, 1.0, -1.0, 0.0, 1.0],
+        _ => [1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+    }
+}
+
+///
+/// Get the rows of a buffer with the given transform that a crop of the upright buffer covers
+///
+/// # Arguments
+///
+/// * `crop` - The region of the upright buffer as x, y, width and height relative to its size, or `None` for all of it
+/// * `transform` - The transform of the buffer contents
+/// * `height` - The height of the buffer in pixels
+///
+fn buffer_rows(crop: Option<(f32, f32, f32, f32)>, transform: Transform, height: u32) -> (u32, u32) {
+    let Some((x, y, width, crop_height)) = crop else {
+        return (0, height);
+    };
+
+    // map the corners of the crop into the buffer and take the rows between them
+    let [_, _, _, d, e, f] = buffer_coordinates(transform);
+    let rows = [(x, y), (x + width, y), (x, y + crop_height), (x + width, y + crop_height)].map(|(u, v)| d * u + e * v + f);
+    let top = (rows.iter().copied().fold(1.0, f32::min) * height as f32).floor().clamp(0.0, height as f32) as u32;
+    let bottom = (rows.iter().copied().fold(0.0, f32::max) * height as f32).ceil().clamp(0.0, height as f32) as u32;
+    (top, bottom.saturating_sub(top))
+}
+
 ///
 /// EGL-based led render pipeline
 ///
@@ -22,10 +228,15 @@ pub struct RenderPipeline {
     env: Environment,
     _libgl: SharedObject,
 
-    textures: HashMap<u64, Texture>, // screen textures
-    shader_program: HashMap<u64, (Shader, Framebuffer)>, // active shader program
+    textures: HashMap<u64, Source>, // screen textures
+    regions: HashMap<u64, (u64, [f32; 4])>, // textures cut from a shared screen texture, with the region they sample
+    composites: HashMap<u64, Composite>, // textures composed of regions of shared screen textures
+    background: [f32; 3], // color of the regions of composed textures no shared texture covers
+    shader_program: HashMap<u64, Program>, // active shader program
 
     vertex_array: Option<VertexArrayObject>, // vertex array object
+    view_shader: Option<Shader>, // shader turning screencopy textures upright
+    uv_transform: i32 // location of the uv_transform uniform of the view shader
 }
 
 impl RenderPipeline {
@@ -63,6 +274,11 @@ impl RenderPipeline {
         vertex_array.bind();
         debug!("created vertex array object");
 
+        // create view shader
+        let view_shader = Shader::from_source(VIEW_VERTEX_SHADER, VIEW_FRAGMENT_SHADER, &[0]).context("failed to create view shader")?;
+        let uv_transform = view_shader.uniform_location("uv_transform");
+        debug!("created view shader: program={}", view_shader.id);
+
         // setup opengl
         unsafe {
             gl::Enable(gl::TEXTURE_2D);
@@ -73,47 +289,229 @@ impl RenderPipeline {
             env,
             _libgl,
             textures: HashMap::new(),
+            regions: HashMap::new(),
+            composites: HashMap::new(),
+            background: [0.0; 3],
             shader_program: HashMap::new(),
-            vertex_array: Some(vertex_array)
+            vertex_array: Some(vertex_array),
+            view_shader: Some(view_shader),
+            uv_transform
         })
     }
 
     ///
-    /// Update a specific screencopy texture with a buffer object
+    /// Create a screencopy texture from a buffer object
     ///
     /// # Arguments
     ///
-    /// * `tid` - The texture id
     /// * `bo` - The buffer object
+    /// * `crop` - The region of the upright buffer object to use as x, y, width and height relative to its size, or `None` to use all of it
+    /// * `transform` - The transform of the buffer contents, which is undone before shaders sample the texture
     ///
     /// # Errors
     ///
     /// This function will return an error if the texture cannot be created from the buffer object
     ///
-    pub fn set_texture(&mut self, tid: u64, bo: &gbm::BufferObject<()>) -> Result<(), anyhow::Error> {
+    pub fn create_texture(&self, bo: &gbm::BufferObject<()>, crop: Option<(f32, f32, f32, f32)>, transform: Transform) -> Result<Source, anyhow::Error> {
+        let planes = (0..bo.plane_count().context("failed to query planes of buffer object")? as i32)
+            .map(|plane| Ok((
+                bo.fd_for_plane(plane).context("failed to export plane of buffer object")?,
+                bo.offset(plane).context("failed to query offset of plane")?,
+                bo.stride_for_plane(plane).context("failed to query stride of plane")?
+            )))
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
         let texture = Texture::new_from_dmabuf(
             self.env.get_display(),
-            bo.fd_for_plane(0).unwrap(),
+            &planes,
             bo.width().unwrap(),
             bo.height().unwrap(),
             bo.format().unwrap() as u32,
-            bo.offset(0).unwrap(),
-            bo.stride_for_plane(0).unwrap(),
             bo.modifier().unwrap().into()
         ).map_err(|e| anyhow!(e))?;
-        debug!("created new texture from dmabuf: tid={}, bo={:?}", tid, bo);
+        debug!("created new texture from dmabuf: texture={}, bo={:?}", texture.id, bo);
+
+        Ok(RenderPipeline::create_source(Framebuffer::from_texture(texture, bo.width().unwrap(), bo.height().unwrap()), crop, transform))
+    }
+
+    ///
+    /// Create a screencopy texture for pixels in shared memory, which are uploaded with `update_sources` after every capture
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the captured buffer
+    /// * `height` - The height of the captured buffer
+    /// * `crop` - The region of the upright buffer to use as x, y, width and height relative to its size, or `None` to use all of it
+    /// * `transform` - The transform of the buffer contents, which is undone before shaders sample the texture
+    ///
+    pub fn create_shm_texture(&self, width: u32, height: u32, crop: Option<(f32, f32, f32, f32)>, transform: Transform) -> Source {
+        let framebuffer = Framebuffer::new(width, height);
+        debug!("created new texture for shared memory: texture={}, size={}x{}", framebuffer.color.id, width, height);
+
+        RenderPipeline::create_source(framebuffer, crop, transform)
+    }
+
+    fn create_source(framebuffer: Framebuffer, crop: Option<(f32, f32, f32, f32)>, transform: Transform) -> Source {
+        let crop = crop.filter(|crop| *crop != (0.0, 0.0, 1.0, 1.0));
+        let rows = buffer_rows(crop, transform, framebuffer.height);
+        if crop.is_none() && transform == Transform::Normal {
+            return Source { framebuffer, view: None, rows };
+        }
+
+        // size of the buffer once turned upright
+        let (width, height) = match transform {
+            Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270 => (framebuffer.height, framebuffer.width),
+            _ => (framebuffer.width, framebuffer.height)
+        };
+
+        // map the texture coordinates into the crop, then into the buffer
+        let (x, y, crop_width, crop_height) = crop.unwrap_or((0.0, 0.0, 1.0, 1.0));
+        let [a, b, c, d, e, f] = buffer_coordinates(transform);
+        let matrix = [
+            a * crop_width, b * crop_height, a * x + b * y + c,
+            d * crop_width, e * crop_height, d * x + e * y + f,
+            0.0, 0.0, 1.0
+        ];
+
+        let view_width = ((width as f32 * crop_width).round() as u32).max(1);
+        let view_height = ((height as f32 * crop_height).round() as u32).max(1);
+        let view = Framebuffer::new(view_width, view_height);
+        debug!("created upright view of texture: framebuffer={}, size={}x{}, crop={:?}, transform={:?}", view.id, view_width, view_height, crop, transform);
+
+        Source { framebuffer, view: Some((view, matrix)), rows }
+    }
+
+    ///
+    /// Update the screencopy textures with the frames captured since the last update
+    ///
+    /// Frames captured into dmabufs are already in their textures, frames captured into shared memory are uploaded,
+    /// limited to the rows of the captured buffer that are sampled.
+    ///
+    /// # Arguments
+    ///
+    /// * `screencopy` - The screencopy instance the textures were created for
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the pixels of a frame cannot be uploaded
+    ///
+    pub fn update_sources(&self, screencopy: &Screencopy) -> Result<(), anyhow::Error> {
+        for (tid, shm) in screencopy.shm_frames() {
+            if let Some(source) = self.textures.get(&tid) {
+                source.upload(shm).with_context(|| format!("failed to upload frame of texture {:#x}", tid))?;
+            }
+        }
 
-        self.textures.insert(tid, texture);
         Ok(())
     }
 
     ///
-    /// Update the shader program
+    /// Update a specific screencopy texture
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - The texture id
+    /// * `texture` - The texture created with `create_texture`
+    ///
+    pub fn set_texture(&mut self, tid: u64, texture: Source) {
+        self.textures.insert(tid, texture);
+        self.remove_cut_texture(tid);
+    }
+
+    ///
+    /// Cut a screencopy texture from a shared texture, which shaders sample through its `textureNRect` uniform
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - The texture id
+    /// * `source` - The id of the shared texture, set with `set_texture`
+    /// * `rect` - The region of the shared texture as x, y, width and height relative to its size
+    ///
+    pub fn set_region(&mut self, tid: u64, source: u64, rect: (f32, f32, f32, f32)) {
+        self.textures.remove(&tid);
+        let previous = self.composites.remove(&tid).map(|composite| composite.parts).unwrap_or_default();
+        if let Some((previous, _)) = self.regions.insert(tid, (source, [rect.0, rect.1, rect.2, rect.3])) {
+            self.prune_shared_texture(previous);
+        }
+        for (previous, _, _) in previous {
+            self.prune_shared_texture(previous);
+        }
+    }
+
+    ///
+    /// Compose a screencopy texture of regions of shared textures, which shaders sample like any other texture
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - The texture id
+    /// * `width` - The width of the composed texture
+    /// * `height` - The height of the composed texture
+    /// * `parts` - The ids of the shared textures, set with `set_texture`, with the region sampled from each and the region of the
+    ///   composed texture it covers, both as x, y, width and height relative to the size of the texture
+    ///
+    pub fn set_composite(&mut self, tid: u64, width: u32, height: u32, parts: Vec<(u64, [f32; 4], [f32; 4])>) {
+        self.textures.remove(&tid);
+        let framebuffer = Framebuffer::new(width.max(1), height.max(1));
+        debug!("created composed texture: framebuffer={}, size={}x{}, parts={}", framebuffer.id, width, height, parts.len());
+        let previous = self.composites.insert(tid, Composite { framebuffer, parts }).map(|composite| composite.parts).unwrap_or_default();
+        if let Some((previous, _)) = self.regions.remove(&tid) {
+            self.prune_shared_texture(previous);
+        }
+        for (previous, _, _) in previous {
+            self.prune_shared_texture(previous);
+        }
+    }
+
+    ///
+    /// Remove the region or composite of a texture, pruning the shared textures it used
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - The texture id
+    ///
+    fn remove_cut_texture(&mut self, tid: u64) {
+        let mut sources = self.regions.remove(&tid).map(|(source, _)| vec![source]).unwrap_or_default();
+        sources.extend(self.composites.remove(&tid).into_iter().flat_map(|composite| composite.parts).map(|(source, _, _)| source));
+        for source in sources {
+            self.prune_shared_texture(source);
+        }
+    }
+
+    ///
+    /// Remove a shared texture once no texture is cut from it or composed of it anymore
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The id of the shared texture
+    ///
+    fn prune_shared_texture(&mut self, source: u64) {
+        let used = self.regions.values().any(|(region_source, _)| *region_source == source)
+            || self.composites.values().any(|composite| composite.parts.iter().any(|(part_source, _, _)| *part_source == source));
+        if !used {
+            trace!("removing unused shared texture {:#x}", source);
+            self.textures.remove(&source);
+        }
+    }
+
+    ///
+    /// Remove a specific screencopy texture
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - The texture id
+    ///
+    pub fn remove_texture(&mut self, tid: u64) {
+        self.textures.remove(&tid);
+        self.remove_cut_texture(tid);
+    }
+
+    ///
+    /// Create a shader program
     ///
     /// # Arguments
     ///
-    /// * `sid` - The shader id
     /// * `tids` - The texture ids
+    /// * `width` - The width of the framebuffer
+    /// * `height` - The height of the framebuffer
     /// * `vert` - The vertex shader path
     /// * `frag` - The fragment shader path
     ///
@@ -121,13 +519,61 @@ impl RenderPipeline {
     ///
     /// This function will return an error if the shader program cannot be created
     ///
-    pub fn set_shader(&mut self, sid: u64, tids: &[u64], width: u32, height: u32, vert: &PathBuf, frag: &PathBuf) -> Result<(), anyhow::Error> {
-        let shader_program = Shader::new(vert, frag, tids).context("failed to create shader program")?;
+    pub fn create_shader(&self, tids: &[u64], width: u32, height: u32, vert: &PathBuf, frag: &PathBuf) -> Result<Program, anyhow::Error> {
+        let shader = Shader::new(vert, frag, tids).context("failed to create shader program")?;
         let framebuffer = Framebuffer::new(width, height);
-        debug!("created new shader program: sid={}, framebuffer={}", sid, framebuffer.id);
+        debug!("created new shader program: program={}, framebuffer={}", shader.id, framebuffer.id);
 
-        self.shader_program.insert(sid, (shader_program, framebuffer));
-        Ok(())
+        Ok(Program { shader, framebuffer, rendered: false })
+    }
+
+    ///
+    /// Update the shader program
+    ///
+    /// # Arguments
+    ///
+    /// * `sid` - The shader id
+    /// * `program` - The shader program created with `create_shader`
+    ///
+    pub fn set_shader(&mut self, sid: u64, program: Program) {
+        self.shader_program.insert(sid, program);
+    }
+
+    ///
+    /// Remove a shader program
+    ///
+    /// # Arguments
+    ///
+    /// * `sid` - The shader id
+    ///
+    pub fn remove_shader(&mut self, sid: u64) {
+        self.shader_program.remove(&sid);
+    }
+
+    ///
+    /// Check whether a shader program has to be rendered even if its textures are unchanged
+    ///
+    /// This is the case for programs that were never rendered and for animated shaders using the `time` uniform.
+    ///
+    /// # Arguments
+    ///
+    /// * `sid` - The shader id
+    ///
+    pub fn needs_render(&self, sid: u64) -> bool {
+        self.shader_program.get(&sid).is_some_and(|program| !program.rendered || program.shader.is_animated())
+    }
+
+    ///
+    /// Render a shader program on the next frame even if its textures are unchanged
+    ///
+    /// # Arguments
+    ///
+    /// * `sid` - The shader id
+    ///
+    pub fn invalidate(&mut self, sid: u64) {
+        if let Some(program) = self.shader_program.get_mut(&sid) {
+            program.rendered = false;
+        }
     }
 
     ///
@@ -138,9 +584,23 @@ impl RenderPipeline {
     /// * `sid` - The shader id
     /// * `pixels` - The pixel buffer
     ///
-    pub fn render(&self, sid: u64, pixels: &mut [u8]) {
-        let (shader, framebuffer) = self.shader_program.get(&sid).unwrap();
-        let textures = shader.tids.iter().map(|tid| self.textures.get(tid).unwrap()).collect::<Vec<&Texture>>();
+    pub fn render(&mut self, sid: u64, pixels: &mut [u8]) {
+        let Program { shader, framebuffer, .. } = self.shader_program.get(&sid).unwrap();
+        let (tids, rects): (Vec<u64>, Vec<[f32; 4]>) = shader.tids.iter()
+            .map(|tid| self.regions.get(tid).copied().unwrap_or((*tid, FULL_RECT)))
+            .unzip();
+        let view_shader = self.view_shader.as_ref().unwrap();
+        let textures = tids.iter().map(|tid| match self.composites.get(tid) {
+            Some(composite) => {
+                composite.update(&self.textures, self.background, view_shader, self.uv_transform);
+                &composite.framebuffer.color
+            },
+            None => {
+                let source = self.textures.get(tid).unwrap();
+                source.update(view_shader, self.uv_transform);
+                source.texture()
+            }
+        }).collect::<Vec<&Texture>>();
 
         framebuffer.bind();
 
@@ -148,7 +608,7 @@ impl RenderPipeline {
             gl::Clear(gl::COLOR_BUFFER_BIT);
         }
 
-        shader.bind(&textures);
+        shader.bind(&textures, &rects);
 
         unsafe {
             gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
@@ -162,6 +622,18 @@ impl RenderPipeline {
         trace!("read pixels from framebuffer: sid={}, framebuffer={}", sid, framebuffer.id);
 
         framebuffer.unbind();
+        self.shader_program.get_mut(&sid).unwrap().rendered = true;
+    }
+
+    ///
+    /// Set the color of the regions of composed textures that no shared texture covers, e.g. gaps between outputs
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The color (r, g, b)
+    ///
+    pub fn set_background(&mut self, color: [u8; 3]) {
+        self.background = color.map(|channel| channel as f32 / 255.0);
     }
 
     ///
@@ -169,6 +641,8 @@ impl RenderPipeline {
     ///
     pub fn reset(&mut self) {
         self.textures.clear();
+        self.regions.clear();
+        self.composites.clear();
         self.shader_program.clear();
     }
 
@@ -178,8 +652,69 @@ impl Drop for RenderPipeline {
     fn drop(&mut self) {
         debug!("dropping render pipeline, this will destroy all gl objects as well as the egl environment");
         self.vertex_array.as_ref().unwrap().unbind();
-        self.textures.clear();
-        self.shader_program.clear();
+        self.reset();
+        self.view_shader = None;
         self.vertex_array = None;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use wayland_client::protocol::wl_output::Transform;
+
+    use crate::screencopy::{flip_vertically, upright_rect};
+
+    use super::{buffer_coordinates, buffer_rows};
+
+    const TRANSFORMS: [Transform; 8] = [
+        Transform::Normal, Transform::_90, Transform::_180, Transform::_270,
+        Transform::Flipped, Transform::Flipped90, Transform::Flipped180, Transform::Flipped270
+    ];
+
+    const CORNERS: [(f32, f32); 4] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)];
+
+    fn buffer_point(coordinates: [f32; 6], (u, v): (f32, f32)) -> (f32, f32) {
+        let [a, b, c, d, e, f] = coordinates;
+        (a * u + b * v + c, d * u + e * v + f)
+    }
+
+    #[test]
+    fn upright_rect_inverts_buffer_coordinates() {
+        let size = (40, 20);
+        for transform in TRANSFORMS {
+            for corner in CORNERS {
+                let (x, y) = buffer_point(buffer_coordinates(transform), corner);
+                let rect = ((x * size.0 as f32) as i32, (y * size.1 as f32) as i32, 0, 0);
+                let (u, v, _, _) = upright_rect(transform, rect, size);
+                assert_eq!((u, v), corner, "corner {:?} of {:?} maps back to ({}, {})", corner, transform, u, v);
+            }
+        }
+    }
+
+    #[test]
+    fn flip_vertically_inverts_buffer_rows() {
+        for transform in TRANSFORMS {
+            for corner in CORNERS {
+                let (x, y) = buffer_point(buffer_coordinates(transform), corner);
+                let flipped = buffer_point(buffer_coordinates(flip_vertically(transform)), corner);
+                assert_eq!(flipped, (x, 1.0 - y), "corner {:?} of {:?}", corner, transform);
+            }
+        }
+    }
+
+    #[test]
+    fn upright_rect_covers_entire_buffer() {
+        for transform in TRANSFORMS {
+            assert_eq!(upright_rect(transform, (0, 0, 40, 20), (40, 20)), (0.0, 0.0, 1.0, 1.0), "{:?}", transform);
+        }
+    }
+
+    #[test]
+    fn buffer_rows_of_top_edge() {
+        let crop = Some((0.0, 0.0, 1.0, 0.1));
+        assert_eq!(buffer_rows(crop, Transform::Normal, 100), (0, 10));
+        assert_eq!(buffer_rows(crop, Transform::_180, 100), (90, 10));
+        assert_eq!(buffer_rows(crop, Transform::_90, 100), (0, 100));
+        assert_eq!(buffer_rows(None, Transform::_270, 100), (0, 100));
+    }
+}
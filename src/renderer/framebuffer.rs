@@ -24,9 +24,21 @@ impl Framebuffer {
     /// * `height` - Height of the framebuffer
     ///
     pub fn new(width: GLuint, height: GLuint) -> Framebuffer {
+        Framebuffer::from_texture(Texture::new(width, height), width, height)
+    }
+
+    ///
+    /// Create a new Framebuffer around an existing texture
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - Color texture of the framebuffer
+    /// * `width` - Width of the texture
+    /// * `height` - Height of the texture
+    ///
+    pub fn from_texture(color: Texture, width: GLuint, height: GLuint) -> Framebuffer {
         // create framebuffer
         let framebuffer = unsafe { Framebuffer::create_bound_framebuffer() };
-        let color = Texture::new(width, height);
         trace!("created framebuffer: framebuffer={}, color={}", framebuffer, color.id);
 
         // attach color texture
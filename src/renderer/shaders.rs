@@ -14,6 +14,7 @@ pub struct Shader {
     pub tids: Vec<u64>,
     start_time: std::time::Instant,
     time_uniform: i32,
+    rect_uniforms: Vec<i32>, // textureNRect uniforms
 }
 
 impl Shader {
@@ -38,10 +39,27 @@ impl Shader {
         let fragment_shader_source = fs::read_to_string(fragment_shader).context("failed to read fragment shader")?;
         trace!("read fragment shader: {:?}", fragment_shader);
 
+        Shader::from_source(&vertex_shader_source, &fragment_shader_source, tids)
+    }
+
+    ///
+    /// Create a new Shader Program from source
+    ///
+    /// # Arguments
+    ///
+    /// * `vertex_shader_source` - Source of the vertex shader
+    /// * `fragment_shader_source` - Source of the fragment shader
+    /// * `tids` - Texture IDs (do not have to exist yet)
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the shaders fail to compile/link
+    ///
+    pub fn from_source(vertex_shader_source: &str, fragment_shader_source: &str, tids: &[u64]) -> Result<Self, anyhow::Error> {
         // compile shaders
-        let vertex_shader = unsafe { Shader::compile_shader(&vertex_shader_source, gl::VERTEX_SHADER).map_err(|e| anyhow!(e))? };
+        let vertex_shader = unsafe { Shader::compile_shader(vertex_shader_source, gl::VERTEX_SHADER).map_err(|e| anyhow!(e))? };
         trace!("compiled vertex shader: id={}", vertex_shader);
-        let fragment_shader = unsafe { Shader::compile_shader(&fragment_shader_source, gl::FRAGMENT_SHADER).map_err(|e| anyhow!(e))? };
+        let fragment_shader = unsafe { Shader::compile_shader(fragment_shader_source, gl::FRAGMENT_SHADER).map_err(|e| anyhow!(e))? };
         trace!("compiled fragment shader: id={}", fragment_shader);
 
         // create shader program
@@ -55,6 +73,10 @@ impl Shader {
         }
 
         // set uniform locations
+        let rect_uniforms = tids.iter().enumerate().map(|(i, _)| {
+            let c_rect_i = CString::new(format!("texture{}Rect", i)).unwrap();
+            unsafe { gl::GetUniformLocation(id, c_rect_i.as_ptr()) }
+        }).collect();
         let time_uniform = unsafe {
             gl::UseProgram(id);
             for i in 0..tids.len() {
@@ -71,7 +93,37 @@ impl Shader {
         };
 
         let start_time = std::time::Instant::now();
-        Ok(Self { id, tids: tids.to_vec(), start_time, time_uniform })
+        Ok(Self { id, tids: tids.to_vec(), start_time, time_uniform, rect_uniforms })
+    }
+
+    ///
+    /// Get the location of a uniform
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the uniform
+    ///
+    pub fn uniform_location(&self, name: &str) -> i32 {
+        let c_name = CString::new(name).unwrap();
+        unsafe { gl::GetUniformLocation(self.id, c_name.as_ptr()) }
+    }
+
+    ///
+    /// Check whether the shader is animated, i.e. uses the `time` uniform
+    ///
+    pub fn is_animated(&self) -> bool {
+        self.time_uniform != -1
+    }
+
+    ///
+    /// Check whether the shader declares the `textureNRect` uniform of a texture, i.e. can sample a region cut from a shared texture
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the texture
+    ///
+    pub fn has_rect_uniform(&self, index: usize) -> bool {
+        self.rect_uniforms.get(index).is_some_and(|uniform| *uniform != -1)
     }
 
     unsafe fn compile_shader(source: &str, shader_type: GLenum) -> Result<GLuint, &'static str> {
@@ -136,8 +188,9 @@ impl Shader {
     /// # Arguments
     ///
     /// * `textures` - The textures to bind
+    /// * `rects` - The regions of the textures to sample as x, y, width and height in texture coordinates
     ///
-    pub fn bind(&self, textures: &Vec<&Texture>) {
+    pub fn bind(&self, textures: &Vec<&Texture>, rects: &[[f32; 4]]) {
         if textures.len() != self.tids.len() {
             return;
         }
@@ -149,6 +202,9 @@ impl Shader {
                 gl::ActiveTexture(gl::TEXTURE0 + i as u32);
                 texture.bind();
             }
+            for (uniform, rect) in self.rect_uniforms.iter().zip(rects) {
+                gl::Uniform4fv(*uniform, 1, rect.as_ptr());
+            }
 
             let time = self.start_time.elapsed().as_secs_f32();
             gl::Uniform1f(self.time_uniform, time);
@@ -4,6 +4,9 @@ use egls::{egl, EGLDisplay, EGLImageKHR};
 use gl::types::{GLenum, GLint, GLuint};
 use log::trace;
 
+/// Modifier of dmabufs with an implicit layout
+pub const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
 ///
 /// OpenGL Texture
 ///
@@ -21,42 +24,47 @@ impl Texture {
     /// # Arguments
     ///
     /// * `dpy` - EGL Display
-    /// * `dmabuf` - File descriptor of the dmabuf
+    /// * `planes` - File descriptor, offset and stride of each plane of the dmabuf (at most 4)
     /// * `width` - Width of the texture
     /// * `height` - Height of the texture
     /// * `format` - Format of the dmabuf
-    /// * `offset` - Offset of the dmabuf
-    /// * `stride` - Stride of the dmabuf
-    /// * `modifiers` - Modifiers of the dmabuf
+    /// * `modifier` - Modifier of the dmabuf, `DRM_FORMAT_MOD_INVALID` for an implicit layout
     ///
     /// # Errors
     ///
     /// This function will return an error if the egl image cannot be created from the dmabuf
     ///
-    pub fn new_from_dmabuf(dpy: EGLDisplay, dmabuf: OwnedFd, width: u32, height: u32, format: u32, offset: u32, stride: u32, modifiers: u64) -> Result<Self, &'static str> {
+    pub fn new_from_dmabuf(dpy: EGLDisplay, planes: &[(OwnedFd, u32, u32)], width: u32, height: u32, format: u32, modifier: u64) -> Result<Self, &'static str> {
+        if planes.is_empty() || planes.len() > egl2::DMA_BUF_PLANES.len() {
+            return Err("unsupported number of dmabuf planes");
+        }
+
+        // describe every plane of the dmabuf
+        let mut attributes = vec![
+            egl::WIDTH as i32, width as i32,
+            egl::HEIGHT as i32, height as i32,
+            egl2::LINUX_DRM_FOURCC_EXT as i32, format as i32
+        ];
+        for ((fd, offset, stride), [fd_ext, offset_ext, pitch_ext, modifier_lo_ext, modifier_hi_ext]) in planes.iter().zip(egl2::DMA_BUF_PLANES) {
+            attributes.extend([fd_ext as i32, fd.as_raw_fd(), offset_ext as i32, *offset as i32, pitch_ext as i32, *stride as i32]);
+            if modifier != DRM_FORMAT_MOD_INVALID {
+                attributes.extend([modifier_lo_ext as i32, modifier as i32, modifier_hi_ext as i32, (modifier >> 32) as i32]);
+            }
+        }
+        attributes.extend([egl::IMAGE_PRESERVED_KHR as i32, 1, egl::NONE as i32]);
+
         // create egl image from dmabuf
         let image = unsafe { egl2::CreateImageKHR(
             dpy,
             egl::NO_CONTEXT,
             egl2::LINUX_DMA_BUF_EXT,
             ptr::null(),
-            [
-                egl::WIDTH as i32, width as i32,
-                egl::HEIGHT as i32, height as i32,
-                egl2::LINUX_DRM_FOURCC_EXT as i32, format as i32,
-                egl2::DMA_BUF_PLANE0_FD_EXT as i32, dmabuf.as_raw_fd() as i32,
-                egl2::DMA_BUF_PLANE0_OFFSET_EXT as i32, offset as i32,
-                egl2::DMA_BUF_PLANE0_PITCH_EXT as i32, stride as i32,
-                egl2::DMA_BUF_PLANE0_MODIFIER_LO_EXT as i32, modifiers as i32,
-                egl2::DMA_BUF_PLANE0_MODIFIER_HI_EXT as i32, (modifiers >> 32) as i32,
-                egl::IMAGE_PRESERVED_KHR as i32, 1,
-                egl::NONE as i32
-            ].as_ptr()
+            attributes.as_ptr()
         )};
         if image == egl::NO_IMAGE_KHR {
             return Err("failed to create image from dmabuf");
         }
-        trace!("created egl image from dmabuf: image={:?}", image);
+        trace!("created egl image from dmabuf: image={:?}, planes={}", image, planes.len());
 
         // create texture from egl image
         let texture = unsafe { Texture::create_bound_texture(gl::TEXTURE_2D) };
@@ -98,6 +106,48 @@ impl Texture {
         }
     }
 
+    ///
+    /// Upload pixels into the texture
+    ///
+    /// # Arguments
+    ///
+    /// * `pixels` - The pixels to upload, starting at the first row
+    /// * `width` - Width of the texture
+    /// * `rows` - The first row and the number of rows of the texture to upload
+    /// * `stride` - Bytes per row of the pixels
+    /// * `format` - The drm fourcc of the pixels
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the format is not supported or there are not enough pixels
+    ///
+    pub fn upload(&self, pixels: &[u8], width: u32, rows: (u32, u32), stride: u32, format: u32) -> Result<(), &'static str> {
+        // little endian drm formats are stored in reverse order
+        let (gl_format, opaque) = match &format.to_le_bytes() {
+            b"AR24" => (gl::BGRA, false),
+            b"XR24" => (gl::BGRA, true),
+            b"AB24" => (gl::RGBA, false),
+            b"XB24" => (gl::RGBA, true),
+            _ => return Err("unsupported pixel format")
+        };
+        let (first, height) = rows;
+        if stride < width * 4 || pixels.len() < stride as usize * height as usize {
+            return Err("pixel buffer is too small");
+        }
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_A, if opaque { gl::ONE } else { gl::ALPHA } as GLint);
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, (stride / 4) as GLint);
+            gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, first as i32, width as i32, height as i32, gl_format, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const std::ffi::c_void);
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+        trace!("uploaded pixels to texture: texture={}, size={}x{}, first row={}", self.id, width, height, first);
+
+        Ok(())
+    }
+
     unsafe fn create_bound_texture(texture_type: GLenum) -> GLuint {
         // generate texture
         let mut texture = 0;
@@ -152,11 +202,14 @@ mod egl2 {
 
     pub const LINUX_DMA_BUF_EXT: EGLenum = 0x3270;
     pub const LINUX_DRM_FOURCC_EXT: EGLenum = 0x3271;
-    pub const DMA_BUF_PLANE0_FD_EXT: EGLenum = 0x3272;
-    pub const DMA_BUF_PLANE0_OFFSET_EXT: EGLenum = 0x3273;
-    pub const DMA_BUF_PLANE0_PITCH_EXT: EGLenum = 0x3274;
-    pub const DMA_BUF_PLANE0_MODIFIER_LO_EXT: EGLenum = 0x3443;
-    pub const DMA_BUF_PLANE0_MODIFIER_HI_EXT: EGLenum = 0x3444;
+
+    /// Fd, offset, pitch, modifier lo and modifier hi attributes of each plane
+    pub const DMA_BUF_PLANES: [[EGLenum; 5]; 4] = [
+        [0x3272, 0x3273, 0x3274, 0x3443, 0x3444],
+        [0x3275, 0x3276, 0x3277, 0x3445, 0x3446],
+        [0x3278, 0x3279, 0x327A, 0x3447, 0x3448],
+        [0x3440, 0x3441, 0x3442, 0x3449, 0x344A]
+    ];
 
     type PFNEGLCREATEIMAGEKHRPROC = extern "C" fn(dpy: EGLDisplay, ctx: EGLContext, target: EGLenum, buffer: *const std::ffi::c_void, attrib_list: *const i32) -> EGLImageKHR;
     static mut CREATE_IMAGE_KHR: Option<PFNEGLCREATEIMAGEKHRPROC> = None;
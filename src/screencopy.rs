@@ -1,12 +1,25 @@
-use std::{collections::HashMap, fs::File, os::fd::{AsRawFd, BorrowedFd}};
+use std::{collections::HashMap, fs::File, os::fd::{AsFd, AsRawFd}, path::PathBuf, time::{Duration, Instant}};
 
 use anyhow::{anyhow, Context};
 use gbm::{BufferObject, BufferObjectFlags, Device};
-use log::{debug, trace};
-use wayland_client::{backend::ObjectId, event_created_child, protocol::{wl_buffer::WlBuffer, wl_output::{self, WlOutput}, wl_registry::{self}}, Connection, Dispatch, Proxy, QueueHandle};
-use wayland_protocols::wp::linux_dmabuf::zv1::client::{zwp_linux_buffer_params_v1::{self, Flags, ZwpLinuxBufferParamsV1}, zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1};
+use log::{debug, info, trace, warn};
+use wayland_client::{backend::{ObjectId, WaylandError}, event_created_child, protocol::{wl_buffer::WlBuffer, wl_output::{self, Transform, WlOutput}, wl_registry::{self}, wl_seat::WlSeat, wl_shm::{self, WlShm}, wl_shm_pool::WlShmPool}, Connection, Dispatch, EventQueue, Proxy, QueueHandle};
+use wayland_protocols::ext::idle_notify::v1::client::{ext_idle_notification_v1::{self, ExtIdleNotificationV1}, ext_idle_notifier_v1::ExtIdleNotifierV1};
+use wayland_protocols::ext::image_capture_source::v1::client::{ext_image_capture_source_v1::ExtImageCaptureSourceV1, ext_output_image_capture_source_manager_v1::ExtOutputImageCaptureSourceManagerV1};
+use wayland_protocols::ext::image_copy_capture::v1::client::{ext_image_copy_capture_frame_v1::{self, ExtImageCopyCaptureFrameV1}, ext_image_copy_capture_manager_v1::{ExtImageCopyCaptureManagerV1, Options}, ext_image_copy_capture_session_v1::{self, ExtImageCopyCaptureSessionV1}};
+use wayland_protocols::wp::linux_dmabuf::zv1::client::{zwp_linux_buffer_params_v1::{self, Flags, ZwpLinuxBufferParamsV1}, zwp_linux_dmabuf_feedback_v1::ZwpLinuxDmabufFeedbackV1, zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1};
+use wayland_protocols::xdg::xdg_output::zv1::client::{zxdg_output_manager_v1::ZxdgOutputManagerV1, zxdg_output_v1::{self, ZxdgOutputV1}};
+use wayland_protocols_wlr::output_power_management::v1::client::{zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1, zwlr_output_power_v1::{self, ZwlrOutputPowerV1}};
 use wayland_protocols_wlr::screencopy::v1::client::{zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1}, zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1};
 
+use crate::renderer::DRM_FORMAT_MOD_INVALID;
+
+mod feedback;
+mod shm;
+
+use feedback::DmabufFeedback;
+pub use shm::ShmBuffer;
+
 ///
 /// # OutputInfo
 ///
@@ -16,30 +29,163 @@ use wayland_protocols_wlr::screencopy::v1::client::{zwlr_screencopy_frame_v1::{s
 pub struct OutputInfo {
     pub name: Option<String>,
     pub description: Option<String>, // likely unset
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>, // parsed from the description, if it follows the "make model serial (name)" convention
     pub mode: Option<(i32, i32, i32)>, // width, height and refresh in mHz
+    pub scale: Option<i32>,
+    pub logical_size: Option<(i32, i32)>, // width and height in scaled coordinates (requires xdg-output)
+    pub position: Option<(i32, i32)>, // position in the global compositor space as reported by wl_output
+    pub logical_position: Option<(i32, i32)>, // position in the global layout in scaled coordinates (requires xdg-output)
+    pub transform: Transform, // transform the compositor applies to the contents of the output
+    pub powered: bool, // if the output is on, off when the monitor went into dpms off (requires wlr-output-power-management)
+    global: u32, // name of the registry global
+    xdg_output: Option<ZxdgOutputV1>,
+    output_power: Option<ZwlrOutputPowerV1>, // only requested for outputs that are captured, since it takes exclusive control of their power mode
+    power_lost: bool, // if the power mode is controlled by another client, it is not requested again
+    done: bool // if wl_output sent its first done event, i.e. all of its initial information
 }
 
 impl OutputInfo {
-    fn default() -> Self { Self { name: None, description: None, mode: None } }
+    fn new(global: u32) -> Self {
+        Self { name: None, description: None, make: None, model: None, serial: None, mode: None, scale: None, logical_size: None, position: None, logical_position: None, transform: Transform::Normal, powered: true, global, xdg_output: None, output_power: None, power_lost: false, done: false }
+    }
+
+    ///
+    /// Parse the serial number out of the description, which neither wl_output nor xdg-output report on their own
+    ///
+    fn parse_serial(&self) -> Option<String> {
+        let (description, make, model) = (self.description.as_deref()?, self.make.as_deref()?, self.model.as_deref()?);
+        let description = match self.name.as_deref() {
+            Some(name) => description.strip_suffix(&format!(" ({})", name)).unwrap_or(description),
+            None => description
+        };
+        let serial = description.strip_prefix(&format!("{} {}", make, model))?.trim();
+        (!serial.is_empty()).then(|| serial.to_string())
+    }
+}
+
+/// DRM formats the renderer can import, in order of preference
+const PREFERRED_FORMATS: [gbm::Format; 4] = [gbm::Format::Xrgb8888, gbm::Format::Argb8888, gbm::Format::Xbgr8888, gbm::Format::Abgr8888];
+
+///
+/// Convert a wl_shm format to a drm fourcc
+///
+/// # Arguments
+///
+/// * `format` - The wl_shm format
+///
+fn shm_format_to_fourcc(format: u32) -> u32 {
+    match format {
+        0 => gbm::Format::Argb8888 as u32,
+        1 => gbm::Format::Xrgb8888 as u32,
+        format => format // all other wl_shm formats are drm fourccs
+    }
+}
+
+///
+/// Compose a transform with a vertical flip of the buffer, e.g. for y-inverted buffers
+///
+/// # Arguments
+///
+/// * `transform` - The transform of the buffer contents
+///
+pub fn flip_vertically(transform: Transform) -> Transform {
+    match transform {
+        Transform::Normal => Transform::Flipped180,
+        Transform::_90 => Transform::Flipped90,
+        Transform::_180 => Transform::Flipped,
+        Transform::_270 => Transform::Flipped270,
+        Transform::Flipped => Transform::_180,
+        Transform::Flipped90 => Transform::_90,
+        Transform::Flipped180 => Transform::Normal,
+        Transform::Flipped270 => Transform::_270,
+        transform => transform
+    }
+}
+
+///
+/// Convert a rectangle of a buffer to the upright output, undoing the transform of the buffer contents
+///
+/// # Arguments
+///
+/// * `transform` - The transform of the buffer contents
+/// * `rect` - The rectangle as x, y, width and height in buffer pixels
+/// * `buffer_size` - The width and height of the buffer
+///
+/// # Returns
+///
+/// The rectangle as x, y, width and height relative to the size of the upright output
+///
+pub fn upright_rect(transform: Transform, rect: (i32, i32, i32, i32), buffer_size: (u32, u32)) -> (f32, f32, f32, f32) {
+    let (width, height) = (buffer_size.0.max(1) as f32, buffer_size.1.max(1) as f32);
+    let upright = |u: f32, v: f32| match transform {
+        Transform::_90 => (1.0 - v, u),
+        Transform::_180 => (1.0 - u, 1.0 - v),
+        Transform::_270 => (v, 1.0 - u),
+        Transform::Flipped => (1.0 - u, v),
+        Transform::Flipped90 => (v, u),
+        Transform::Flipped180 => (u, 1.0 - v),
+        Transform::Flipped270 => (1.0 - v, 1.0 - u),
+        _ => (u, v)
+    };
+    let (x0, y0) = upright(rect.0 as f32 / width, rect.1 as f32 / height);
+    let (x1, y1) = upright((rect.0 + rect.2) as f32 / width, (rect.1 + rect.3) as f32 / height);
+    (x0.min(x1), y0.min(y1), (x1 - x0).abs(), (y1 - y0).abs())
+}
+
+/// Bit set in the ids of shared sessions, which capture an entire output for the sessions cut from it
+const SHARED_SESSION: u64 = 1 << 63;
+
+/// Region as x, y, width and height relative to the size of an output
+pub type RelativeRect = (f32, f32, f32, f32);
+
+/// Render node used if the compositor does not report its gpu
+const DEFAULT_RENDER_NODE: &str = "/dev/dri/renderD128";
+
+/// Modifier of buffers with a plain row-major layout
+const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+
+///
+/// Protocol used to capture outputs
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Backend {
+    Wlr, // zwlr_screencopy_manager_v1
+    Ext // ext_image_copy_capture_manager_v1 with ext_output_image_capture_source_manager_v1
 }
 
 ///
 /// # Screencopy
 ///
 /// The screencopy struct is the main entry point for the screencopy module. It holds the wayland connection and the gbm device.
-/// It also holds the outputs and the required protocols.
+/// It also holds the outputs and the required protocols. Without a gbm device or zwp_linux_dmabuf_v1, frames are captured into shared memory.
 ///
 pub struct Screencopy {
     wl: Connection,
-    gbm: Device<File>,
+    eq: Option<EventQueue<Screencopy>>, // registry, output and frame events, only taken while dispatching
+    gbm: Option<Device<File>>,
+    dmabuf_feedback: DmabufFeedback, // default feedback of the compositor, empty if not supported
     sessions: HashMap<u64, CaptureSession>,
 
     // wayland objects
-    pub outputs: HashMap<WlOutput, OutputInfo>,
+    pub outputs: HashMap<WlOutput, OutputInfo>, // outputs whose initial information is complete
+    pending_outputs: HashMap<WlOutput, OutputInfo>, // outputs that were announced but whose information is still incomplete
+    outputs_changed: bool, // if an output was added, removed or updated since the last poll
+    wl_seat: Option<WlSeat>,
+    idle_notification: Option<ExtIdleNotificationV1>,
+    idle: bool, // if the user is idle according to the idle notification
 
     // wayland protocols
+    backend: Backend,
     wlr_screencopy_manager: Option<ZwlrScreencopyManagerV1>,
-    wp_linux_dmabuf: Option<ZwpLinuxDmabufV1>
+    ext_output_source_manager: Option<ExtOutputImageCaptureSourceManagerV1>,
+    ext_image_copy_capture_manager: Option<ExtImageCopyCaptureManagerV1>,
+    xdg_output_manager: Option<ZxdgOutputManagerV1>,
+    wp_linux_dmabuf: Option<ZwpLinuxDmabufV1>,
+    wl_shm: Option<WlShm>,
+    ext_idle_notifier: Option<ExtIdleNotifierV1>,
+    wlr_output_power_manager: Option<ZwlrOutputPowerManagerV1>
 }
 
 impl Screencopy {
@@ -47,23 +193,20 @@ impl Screencopy {
     ///
     /// Create a new Screencopy
     ///
-    /// This will create a gbm device as well as a wayland connection and populate the wayland registry and outputs.
+    /// This will create a wayland connection, populate the wayland registry and outputs and create a gbm device.
+    /// Unless overridden, the gbm device is the render node of the gpu the compositor renders with, as reported by its dmabuf feedback.
     ///
     /// # Arguments
     ///
-    /// * `gbm_device` - The path to the gbm device
+    /// * `gbm_device` - The path to the gbm device, or `None` to pick it automatically
     ///
     /// # Errors
     ///
-    /// This function will return an error if the drm device cannot be opened, the gbm device cannot be created,
-    /// the wayland connection cannot be established, the required protocols are not present or if the registry roundtrip fails.
+    /// This function will return an error if the wayland connection cannot be established, the required protocols are not present
+    /// or if the registry roundtrip fails. If the gbm device cannot be created, dmabuf capture is disabled.
+    /// The ext-image-copy-capture protocols are preferred if advertised, otherwise zwlr_screencopy_manager_v1 is required.
     ///
-    pub fn new(gbm_device: String) -> Result<Self, anyhow::Error> {
-        // create the gbm device
-        let drm_device = File::open(&gbm_device).context("failed to open drm device")?;
-        let gbm = Device::new(drm_device).context("failed to create gbm device")?;
-        debug!("created gbm device: {:?}", gbm_device);
-
+    pub fn new(gbm_device: Option<String>) -> Result<Self, anyhow::Error> {
         // create the wayland connection
         let wl = Connection::connect_to_env().context("failed to connect to wayland server")?;
         debug!("connected to wayland server: {:?}", std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_string()));
@@ -72,43 +215,316 @@ impl Screencopy {
         wl.display().get_registry(&eq.handle(), ());
 
         let mut state = Screencopy {
-            wl, gbm, sessions: HashMap::new(),
-            outputs: HashMap::new(),
-            wlr_screencopy_manager: None, wp_linux_dmabuf: None
+            wl, eq: None, gbm: None, sessions: HashMap::new(), dmabuf_feedback: DmabufFeedback::default(),
+            outputs: HashMap::new(), pending_outputs: HashMap::new(), outputs_changed: false,
+            wl_seat: None, idle_notification: None, idle: false,
+            backend: Backend::Wlr,
+            wlr_screencopy_manager: None, ext_output_source_manager: None, ext_image_copy_capture_manager: None,
+            xdg_output_manager: None, wp_linux_dmabuf: None, wl_shm: None, ext_idle_notifier: None,
+            wlr_output_power_manager: None
         };
 
         eq.blocking_dispatch(&mut state).context("failed to complete registry roundtrip")?;
-        eq.blocking_dispatch(&mut state).context("failed to complete output infos roundtrip")?; // fetch outputs after populating registry
+        eq.roundtrip(&mut state).context("failed to complete output infos roundtrip")?; // fetch outputs after populating registry
         debug!("populated wayland registry and discovered {} outputs", state.outputs.len());
 
         // ensure required globals are present
-        if state.wlr_screencopy_manager.is_none() {
-            return Err(anyhow!("no ZwlrScreencopyManagerV1 protocol"));
+        if state.ext_output_source_manager.is_some() && state.ext_image_copy_capture_manager.is_some() {
+            state.backend = Backend::Ext;
+        } else if state.wlr_screencopy_manager.is_none() {
+            return Err(anyhow!("no ExtImageCopyCaptureManagerV1 or ZwlrScreencopyManagerV1 protocol"));
+        }
+        info!("using {:?} screencopy backend", state.backend);
+
+        // learn the gpu of the compositor from its dmabuf feedback
+        if let Some(wp_linux_dmabuf) = state.wp_linux_dmabuf.as_ref().filter(|wp_linux_dmabuf| wp_linux_dmabuf.version() >= 4) {
+            let feedback = wp_linux_dmabuf.get_default_feedback(&eq.handle(), ());
+            while !state.dmabuf_feedback.done {
+                eq.blocking_dispatch(&mut state).context("failed to receive dmabuf feedback")?;
+            }
+            feedback.destroy();
         }
 
-        if state.wp_linux_dmabuf.is_none() {
-            return Err(anyhow!("no ZwpLinuxDmabufV1 protocol"));
+        // create the gbm device
+        let gbm_device = gbm_device.map(PathBuf::from)
+            .or_else(|| state.dmabuf_feedback.render_node())
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_RENDER_NODE));
+        let gbm = File::open(&gbm_device).context("failed to open drm device")
+            .and_then(|drm_device| Device::new(drm_device).context("failed to create gbm device"));
+        state.gbm = match gbm {
+            Ok(gbm) => {
+                info!("created gbm device: {:?}", gbm_device);
+                Some(gbm)
+            },
+            Err(e) => {
+                warn!("dmabuf capture disabled, capturing into shared memory instead: {:?}", e);
+                None
+            }
+        };
+
+        state.eq = Some(eq); // keep receiving output hotplug events
+
+        if state.wp_linux_dmabuf.is_none() && state.wl_shm.is_none() {
+            return Err(anyhow!("no ZwpLinuxDmabufV1 or WlShm protocol"));
         }
 
         Ok(state)
     }
 
     ///
-    /// Set the capture session
+    /// Prepare a capture session by capturing it once, which allocates its buffer
+    ///
+    /// The session is not added to the screencopy, use `set_capture_session` for that.
     ///
     /// # Arguments
     ///
-    /// - `id` - The id of the session
-    /// - `session` - The session to set
+    /// - `session` - The session to prepare
     ///
     /// # Errors
     ///
     /// This function will return an error if the initial capture fails.
     ///
-    pub fn set_capture_session(&mut self, id: u64, session: CaptureSession) -> Result<&gbm::BufferObject<()>, anyhow::Error> {
-        self.sessions.insert(id, session);
-        self.capture(id).context("initial capture failed")?;
-        Ok(self.sessions.get(&id).unwrap().buffer_object.as_ref().unwrap())
+    pub fn prepare_capture_session(&self, mut session: CaptureSession) -> Result<CaptureSession, anyhow::Error> {
+        self.capture_session(&mut session).context("initial capture failed")?;
+        Ok(session)
+    }
+
+    ///
+    /// Set a prepared capture session
+    ///
+    /// # Arguments
+    ///
+    /// - `id` - The id of the session
+    /// - `session` - The prepared session to set
+    ///
+    pub fn set_capture_session(&mut self, id: u64, mut session: CaptureSession) {
+        // the session of the initial capture lives on an event queue that is dropped afterwards, so it is replaced by one
+        // on the main event queue, which follows changes to the buffer constraints and the session being stopped
+        if let (Some(source), Some(manager)) = (&session.image_capture_source, &self.ext_image_copy_capture_manager) {
+            let persistent = manager.create_session(source, Options::empty(), &self.eq.as_ref().unwrap().handle(), id);
+            if let Some(initial) = session.image_copy_session.replace(persistent) {
+                initial.destroy();
+            }
+        }
+
+        let sources = session.sources();
+        if let Some(previous) = self.sessions.insert(id, session) {
+            for source in previous.sources().into_iter().filter(|source| !sources.contains(source)) {
+                self.prune_shared_session(source);
+            }
+        }
+        self.update_output_power();
+    }
+
+    ///
+    /// Remove a capture session
+    ///
+    /// # Arguments
+    ///
+    /// - `id` - The id of the session
+    ///
+    pub fn remove_capture_session(&mut self, id: u64) {
+        for source in self.sessions.remove(&id).map(|session| session.sources()).unwrap_or_default() {
+            self.prune_shared_session(source);
+        }
+        self.update_output_power();
+    }
+
+    ///
+    /// Follow the power mode of the outputs that are captured, and stop following the others
+    ///
+    /// On wlroots, following the power mode of an output gives exclusive control over it, so other clients like wlopm
+    /// cannot switch outputs that are captured anymore.
+    ///
+    fn update_output_power(&mut self) {
+        let Some(output_power_manager) = self.wlr_output_power_manager.as_ref() else {
+            return;
+        };
+
+        let qh = self.eq.as_ref().unwrap().handle();
+        let captured = self.sessions.values().map(|session| session.output.clone()).collect::<Vec<_>>();
+        for (output, info) in self.outputs.iter_mut() {
+            match (captured.contains(output), info.output_power.take()) {
+                (true, None) if !info.power_lost => {
+                    trace!("following power mode of output {:?}", info.name);
+                    info.output_power = Some(output_power_manager.get_output_power(output, &qh, output.clone()));
+                },
+                (false, Some(output_power)) => {
+                    trace!("no longer following power mode of output {:?}", info.name);
+                    output_power.destroy();
+                    info.powered = true;
+                },
+                (_, output_power) => info.output_power = output_power
+            }
+        }
+    }
+
+    ///
+    /// Remove a shared session once no session is cut from it anymore
+    ///
+    /// # Arguments
+    ///
+    /// - `id` - The id of the shared session
+    ///
+    fn prune_shared_session(&mut self, id: u64) {
+        if !self.sessions.values().any(|session| session.sources().contains(&id)) {
+            trace!("removing unused shared session {:#x}", id);
+            self.sessions.remove(&id);
+        }
+    }
+
+    ///
+    /// Get the ids of the sessions whose frames make up a session
+    ///
+    /// This is the session itself, the shared session it is cut from or the shared sessions it is composed of.
+    ///
+    /// # Arguments
+    ///
+    /// - `session` - The id of the session
+    ///
+    fn captured_sessions(&self, session: u64) -> Vec<u64> {
+        match self.sessions.get(&session) {
+            Some(capture_session) if !capture_session.parts.is_empty() => capture_session.sources(),
+            Some(capture_session) => vec![capture_session.source.unwrap_or(session)],
+            None => Vec::new()
+        }
+    }
+
+    ///
+    /// Get the id of the shared session capturing an entire output
+    ///
+    /// The id is derived from the registry global of the output, so it does not collide with the ids of other sessions.
+    ///
+    /// # Arguments
+    ///
+    /// - `output` - The output
+    ///
+    pub fn shared_session_id(&self, output: &WlOutput) -> Option<u64> {
+        self.outputs.get(output).map(|info| SHARED_SESSION | info.global as u64)
+    }
+
+    ///
+    /// Check whether a session exists and can still be captured
+    ///
+    /// # Arguments
+    ///
+    /// - `session` - The id of the session
+    ///
+    pub fn is_usable(&self, session: u64) -> bool {
+        self.sessions.get(&session).is_some_and(|capture_session| !capture_session.fail) && self.has_output(session) && !self.transform_changed(session)
+            && !self.is_outdated(session)
+    }
+
+    ///
+    /// Check whether the compositor changed the buffer constraints of a session, or the shared sessions it is cut from or composed of
+    ///
+    /// Outdated sessions keep capturing into their old buffer until they are recreated, which may fail.
+    ///
+    /// # Arguments
+    ///
+    /// - `session` - The id of the session
+    ///
+    pub fn is_outdated(&self, session: u64) -> bool {
+        self.captured_sessions(session).iter().filter_map(|id| self.sessions.get(id)).any(|capture_session| capture_session.outdated)
+    }
+
+    ///
+    /// Dispatch pending output events without blocking
+    ///
+    /// Outputs that are plugged in are added to `outputs` once all of their information was received, removed outputs are dropped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading or dispatching the events fails.
+    ///
+    pub fn poll_outputs(&mut self) -> Result<bool, anyhow::Error> {
+        let mut eq = self.eq.take().unwrap();
+        let status = self.dispatch_outputs(&mut eq);
+        self.eq = Some(eq);
+        status.context("failed to dispatch output events")?;
+
+        Ok(std::mem::take(&mut self.outputs_changed))
+    }
+
+    fn dispatch_outputs(&mut self, eq: &mut EventQueue<Screencopy>) -> Result<(), anyhow::Error> {
+        eq.flush()?;
+        if let Some(guard) = eq.prepare_read() {
+            match guard.read() {
+                Ok(_) => (),
+                Err(WaylandError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => (),
+                Err(e) => return Err(e.into())
+            }
+        }
+        eq.dispatch_pending(self)?;
+
+        Ok(())
+    }
+
+    ///
+    /// Get the captured region of a capture session as x, y, width and height in logical coordinates
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The id of the session
+    ///
+    pub fn region(&self, session: u64) -> Option<(i32, i32, i32, i32)> {
+        self.sessions.get(&session).map(|session| (session.x, session.y, session.width, session.height))
+    }
+
+    ///
+    /// Get the output of a capture session
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The id of the session
+    ///
+    pub fn output(&self, session: u64) -> Option<&WlOutput> {
+        self.sessions.get(&session).map(|session| &session.output)
+    }
+
+    ///
+    /// Check whether the output of a capture session is still present
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The id of the session
+    ///
+    pub fn has_output(&self, session: u64) -> bool {
+        match self.sessions.get(&session) {
+            Some(capture_session) if !capture_session.parts.is_empty() => capture_session.parts.iter().all(|(source, _)| self.has_output(*source)),
+            Some(capture_session) => self.outputs.contains_key(&capture_session.output),
+            None => false
+        }
+    }
+
+    ///
+    /// Check whether the output of a capture session is powered on
+    ///
+    /// Outputs are considered powered on unless the compositor reports otherwise. Sessions composed of multiple outputs are
+    /// powered on as long as any of their outputs is.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The id of the session
+    ///
+    pub fn is_powered(&self, session: u64) -> bool {
+        match self.sessions.get(&session) {
+            Some(capture_session) if !capture_session.parts.is_empty() => capture_session.parts.iter().any(|(source, _)| self.is_powered(*source)),
+            Some(capture_session) => self.outputs.get(&capture_session.output).is_none_or(|info| info.powered),
+            None => true
+        }
+    }
+
+    ///
+    /// Check whether the output of a capture session was rotated or flipped since it was last captured
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The id of the session
+    ///
+    pub fn transform_changed(&self, session: u64) -> bool {
+        self.captured_sessions(session).iter().filter_map(|id| self.sessions.get(id)).any(|session|
+            self.outputs.get(&session.output).is_some_and(|info| info.transform != session.output_transform))
     }
 
     ///
@@ -125,6 +541,42 @@ impl Screencopy {
         self.sessions.clear();
     }
 
+    ///
+    /// Set the time without user activity after which the user is considered idle
+    ///
+    /// The idle state is updated whenever events are dispatched, see `is_idle`. Inhibitors like playing videos
+    /// keep the user from becoming idle.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The idle timeout, or `None` to never become idle
+    ///
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        if let Some(idle_notification) = self.idle_notification.take() {
+            idle_notification.destroy();
+        }
+        self.idle = false;
+
+        let Some(timeout) = timeout else {
+            return;
+        };
+        let (Some(ext_idle_notifier), Some(wl_seat)) = (&self.ext_idle_notifier, &self.wl_seat) else {
+            warn!("idle detection disabled, no ExtIdleNotifierV1 protocol or seat");
+            return;
+        };
+
+        let timeout = timeout.as_millis().min(u32::MAX as u128) as u32;
+        self.idle_notification = Some(ext_idle_notifier.get_idle_notification(timeout, wl_seat, &self.eq.as_ref().unwrap().handle(), ()));
+        debug!("requested idle notification after {} ms", timeout);
+    }
+
+    ///
+    /// Check whether the user is idle
+    ///
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
 }
 
 impl Drop for Screencopy {
@@ -134,9 +586,35 @@ impl Drop for Screencopy {
         if let Some(wlr_screencopy_manager) = self.wlr_screencopy_manager.as_mut() {
             wlr_screencopy_manager.destroy();
         }
+        if let Some(ext_output_source_manager) = self.ext_output_source_manager.as_mut() {
+            ext_output_source_manager.destroy();
+        }
+        if let Some(ext_image_copy_capture_manager) = self.ext_image_copy_capture_manager.as_mut() {
+            ext_image_copy_capture_manager.destroy();
+        }
+        if let Some(xdg_output_manager) = self.xdg_output_manager.as_mut() {
+            xdg_output_manager.destroy();
+        }
         if let Some(wp_linux_dmabuf) = self.wp_linux_dmabuf.as_mut() {
             wp_linux_dmabuf.destroy();
         }
+        if let Some(wl_shm) = self.wl_shm.as_mut() {
+            wl_shm.release();
+        }
+        if let Some(idle_notification) = self.idle_notification.as_mut() {
+            idle_notification.destroy();
+        }
+        if let Some(ext_idle_notifier) = self.ext_idle_notifier.as_mut() {
+            ext_idle_notifier.destroy();
+        }
+        for info in self.outputs.values() {
+            if let Some(output_power) = info.output_power.as_ref() {
+                output_power.destroy();
+            }
+        }
+        if let Some(wlr_output_power_manager) = self.wlr_output_power_manager.as_mut() {
+            wlr_output_power_manager.destroy();
+        }
     }
 }
 
@@ -148,16 +626,63 @@ impl Dispatch<wl_registry::WlRegistry, ()> for Screencopy {
         if let wl_registry::Event::Global { name, interface, version } = event {
             if interface == WlOutput::interface().name {
                 debug!("found output global");
-                state.outputs.insert(registry.bind::<WlOutput, _, _>(name, version, eq_handle, ()), OutputInfo::default());
+                let output = registry.bind::<WlOutput, _, _>(name, version, eq_handle, ());
+                let mut info = OutputInfo::new(name);
+                info.xdg_output = state.xdg_output_manager.as_ref().map(|xdg_output_manager| xdg_output_manager.get_xdg_output(&output, eq_handle, output.clone()));
+                info.done = version < 2; // wl_output only sends done events since version 2
+                state.pending_outputs.insert(output, info);
             } else if interface == ZwlrScreencopyManagerV1::interface().name {
                 debug!("found screencopy manager global");
                 state.wlr_screencopy_manager = Some(registry.bind::<ZwlrScreencopyManagerV1, _, _>(name, version, eq_handle, ()));
+            } else if interface == ExtOutputImageCaptureSourceManagerV1::interface().name {
+                debug!("found output image capture source manager global");
+                state.ext_output_source_manager = Some(registry.bind::<ExtOutputImageCaptureSourceManagerV1, _, _>(name, version.min(1), eq_handle, ()));
+            } else if interface == ExtImageCopyCaptureManagerV1::interface().name {
+                debug!("found image copy capture manager global");
+                state.ext_image_copy_capture_manager = Some(registry.bind::<ExtImageCopyCaptureManagerV1, _, _>(name, version.min(1), eq_handle, ()));
+            } else if interface == ZxdgOutputManagerV1::interface().name {
+                debug!("found xdg output manager global");
+                let xdg_output_manager = registry.bind::<ZxdgOutputManagerV1, _, _>(name, version.min(3), eq_handle, ());
+                for (output, info) in state.outputs.iter_mut().chain(state.pending_outputs.iter_mut()) {
+                    info.xdg_output = Some(xdg_output_manager.get_xdg_output(output, eq_handle, output.clone()));
+                }
+                state.xdg_output_manager = Some(xdg_output_manager);
+            } else if interface == ZwlrOutputPowerManagerV1::interface().name {
+                debug!("found output power manager global");
+                state.wlr_output_power_manager = Some(registry.bind::<ZwlrOutputPowerManagerV1, _, _>(name, version.min(1), eq_handle, ()));
+            } else if interface == WlShm::interface().name {
+                debug!("found shm global");
+                state.wl_shm = Some(registry.bind::<WlShm, _, _>(name, version.min(2), eq_handle, ()));
+            } else if interface == WlSeat::interface().name && state.wl_seat.is_none() {
+                debug!("found seat global");
+                state.wl_seat = Some(registry.bind::<WlSeat, _, _>(name, version.min(1), eq_handle, ()));
+            } else if interface == ExtIdleNotifierV1::interface().name {
+                debug!("found idle notifier global");
+                state.ext_idle_notifier = Some(registry.bind::<ExtIdleNotifierV1, _, _>(name, version.min(1), eq_handle, ()));
             } else if interface == ZwpLinuxDmabufV1::interface().name {
                 debug!("found linux dmabuf global");
-                state.wp_linux_dmabuf = Some(registry.bind::<ZwpLinuxDmabufV1, _, _>(name, version, eq_handle, ()));
+                state.wp_linux_dmabuf = Some(registry.bind::<ZwpLinuxDmabufV1, _, _>(name, version.min(4), eq_handle, ()));
             }
 
             trace!("new global: name={} interface={} version={}", name, interface, version);
+        } else if let wl_registry::Event::GlobalRemove { name } = event {
+            let output = state.outputs.iter().chain(state.pending_outputs.iter()).find(|(_, info)| info.global == name).map(|(output, _)| output.clone());
+            if let Some(output) = output {
+                let info = state.outputs.remove(&output).or_else(|| state.pending_outputs.remove(&output)).unwrap();
+                info!("output removed: name={:?}", info.name);
+                if let Some(xdg_output) = info.xdg_output {
+                    xdg_output.destroy();
+                }
+                if let Some(output_power) = info.output_power {
+                    output_power.destroy();
+                }
+                if output.version() >= 3 {
+                    output.release();
+                }
+                state.outputs_changed = true;
+            }
+
+            trace!("removed global: name={}", name);
         }
     }
 }
@@ -167,13 +692,24 @@ impl Dispatch<wl_registry::WlRegistry, ()> for Screencopy {
 ///
 impl Dispatch<wl_output::WlOutput, ()> for Screencopy {
     fn event(state: &mut Self, proxy: &wl_output::WlOutput, event: <wl_output::WlOutput as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
-        if let Some(info) = state.outputs.get_mut(proxy) {
+        if let Some(info) = state.output_info(proxy) {
             match event {
                 wl_output::Event::Name { name } => info.name = Some(name),
                 wl_output::Event::Description { description } => info.description = Some(description),
+                wl_output::Event::Geometry { x, y, make, model, transform, .. } => {
+                    info.position = Some((x, y));
+                    info.make = Some(make);
+                    info.model = Some(model);
+                    info.transform = transform.into_result().unwrap_or(Transform::Normal);
+                },
                 wl_output::Event::Mode { width, height, refresh, .. } => info.mode = Some((width, height, refresh)),
+                wl_output::Event::Scale { factor } => info.scale = Some(factor),
                 wl_output::Event::Done { .. } => {
-                    trace!("updated output: name={:?} description={:?} mode={:?}", info.name, info.description, info.mode);
+                    info.serial = info.parse_serial();
+                    info.done = true;
+                    trace!("updated output: name={:?} description={:?} make={:?} model={:?} serial={:?} mode={:?}", info.name, info.description, info.make, info.model, info.serial, info.mode);
+                    state.outputs_changed = true;
+                    state.complete_output(proxy);
                  },
                 _ => {}
             }
@@ -182,6 +718,66 @@ impl Dispatch<wl_output::WlOutput, ()> for Screencopy {
     }
 }
 
+impl Screencopy {
+
+    ///
+    /// Get the information of an output, whether it is complete or still pending
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - The output
+    ///
+    fn output_info(&mut self, output: &WlOutput) -> Option<&mut OutputInfo> {
+        if self.outputs.contains_key(output) {
+            self.outputs.get_mut(output)
+        } else {
+            self.pending_outputs.get_mut(output)
+        }
+    }
+
+    ///
+    /// Expose a pending output in `outputs` once wl_output and xdg-output sent all of its initial information
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - The output
+    ///
+    fn complete_output(&mut self, output: &WlOutput) {
+        let complete = self.pending_outputs.get(output).is_some_and(|info| info.done && (info.xdg_output.is_none() || info.logical_size.is_some()));
+        if let Some(info) = self.pending_outputs.remove(output).filter(|_| complete) {
+            info!("output added: name={:?} description={:?}", info.name, info.description);
+            self.outputs.insert(output.clone(), info);
+            self.outputs_changed = true;
+        }
+    }
+
+}
+
+///
+/// ZxdgOutputV1 dispatch
+///
+impl Dispatch<ZxdgOutputV1, WlOutput> for Screencopy {
+    fn event(state: &mut Self, _: &ZxdgOutputV1, event: <ZxdgOutputV1 as Proxy>::Event, output: &WlOutput, _: &Connection, _: &QueueHandle<Self>) {
+        if let Some(info) = state.output_info(output) {
+            match event {
+                zxdg_output_v1::Event::LogicalPosition { x, y } => {
+                    trace!("updated output logical position: name={:?} position={},{}", info.name, x, y);
+                    info.logical_position = Some((x, y));
+                },
+                zxdg_output_v1::Event::LogicalSize { width, height } => {
+                    trace!("updated output logical size: name={:?} size={}x{}", info.name, width, height);
+                    info.logical_size = Some((width, height));
+                },
+                // only used if wl_output is too old to report them itself
+                zxdg_output_v1::Event::Name { name } => { info.name.get_or_insert(name); },
+                zxdg_output_v1::Event::Description { description } => { info.description.get_or_insert(description); },
+                _ => {}
+            }
+            state.complete_output(output);
+        }
+    }
+}
+
 ///
 /// # Screencopy implementation
 ///
@@ -198,37 +794,204 @@ impl Dispatch<wl_output::WlOutput, ()> for Screencopy {
 ///
 pub struct CaptureSession {
     requested_dmabuf_params: Option<(u32, u32, u32)>, // fourcc, width, height
+    requested_shm_params: Option<(u32, u32, u32, u32)>, // wl_shm format, width, height, stride
     screencopy_frame: Option<ZwlrScreencopyFrameV1>,
     linux_buffer_params: Option<ZwpLinuxBufferParamsV1>,
     buffer_object: Option<BufferObject<()>>,
+    shm_pool: Option<WlShmPool>,
+    shm_buffer: Option<ShmBuffer>,
     buffer: Option<WlBuffer>,
 
+    // ext-image-copy-capture state
+    image_capture_source: Option<ExtImageCaptureSourceV1>,
+    image_copy_session: Option<ExtImageCopyCaptureSessionV1>,
+    constraints: Constraints,
+    new_constraints: Constraints, // constraints received since the last done event of the persistent session
+    crop: Option<(f32, f32, f32, f32)>, // normalized region of the upright buffer to use, if the buffer holds the entire output
+    buffer_transform: Option<Transform>, // transform of the buffer contents, if reported by the compositor
+    output_transform: Transform, // transform of the output at the time of the last capture
+    y_invert: bool, // if the buffer contents are upside down
+    source: Option<u64>, // id of the shared session this region is cut from, instead of being captured on its own
+    parts: Vec<(u64, RelativeRect)>, // shared sessions this region is composed of, with the region of each relative to its output
+    image_copy_frame: Option<ExtImageCopyCaptureFrameV1>, // frame requested with `request_frames`
+    buffer_size: (u32, u32), // width and height of the buffer
+    damage: Option<Vec<(i32, i32, i32, i32)>>, // damaged rectangles of the last frame in buffer pixels, `None` if the entire buffer is damaged
+    copied: bool, // if the last requested frame was copied
+    linear: bool, // if the dmabuf has to use the linear modifier
+    frame_done: bool, // if the current frame is either ready or failed
+
     fail: bool, // if any of the dispatches failed
+    outdated: bool, // if the compositor changed the buffer constraints, the session has to be recreated
     output: WlOutput,
     x: i32, y: i32, width: i32, height: i32
 }
 
-impl CaptureSession {
+///
+/// Buffer constraints advertised by an ext-image-copy-capture session
+///
+#[derive(Default, PartialEq)]
+struct Constraints {
+    buffer_size: Option<(u32, u32)>, // width, height
+    dmabuf_formats: Vec<(u32, Vec<u64>)>, // fourcc, modifiers
+    shm_formats: Vec<u32>, // wl_shm format
+    done: bool
+}
+
+impl Constraints {
 
     ///
-    /// Create a new capture session
-    ///
-    /// Please note that the capture coordinates use scaled coordinates, not pixels. The resulting buffer might differ in size.
+    /// Collect a buffer constraint event of an ext-image-copy-capture session
     ///
     /// # Arguments
     ///
-    /// * `output` - The output to capture
-    /// * `x` - The x position to capture
-    /// * `y` - The y position to capture
-    /// * `width` - The width to capture
-    /// * `height` - The height to capture
+    /// * `event` - The event of the session
     ///
-    pub fn new(output: WlOutput, x: i32, y: i32, width: i32, height: i32) -> Self {
-        CaptureSession {
-            requested_dmabuf_params: None, screencopy_frame: None, linux_buffer_params: None, buffer_object: None, buffer: None,
-            fail: false, output,
-            x, y, width, height
-        }
+    fn handle_event(&mut self, event: ext_image_copy_capture_session_v1::Event) {
+        match event {
+            ext_image_copy_capture_session_v1::Event::BufferSize { width, height } => {
+                self.buffer_size = Some((width, height));
+            },
+            ext_image_copy_capture_session_v1::Event::DmabufFormat { format, modifiers } => {
+                let modifiers = modifiers.chunks_exact(8).map(|modifier| u64::from_ne_bytes(modifier.try_into().unwrap())).collect();
+                self.dmabuf_formats.push((format, modifiers));
+            },
+            ext_image_copy_capture_session_v1::Event::ShmFormat { format } => {
+                self.shm_formats.push(format.into());
+            },
+            ext_image_copy_capture_session_v1::Event::Done => {
+                self.done = true;
+            },
+            _ => {}
+        }
+    }
+
+}
+
+impl CaptureSession {
+
+    ///
+    /// Create a new capture session
+    ///
+    /// Please note that the capture coordinates use scaled coordinates, not pixels. The resulting buffer might differ in size.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - The output to capture
+    /// * `x` - The x position to capture
+    /// * `y` - The y position to capture
+    /// * `width` - The width to capture
+    /// * `height` - The height to capture
+    ///
+    pub fn new(output: WlOutput, x: i32, y: i32, width: i32, height: i32) -> Self {
+        CaptureSession {
+            requested_dmabuf_params: None, requested_shm_params: None, screencopy_frame: None, linux_buffer_params: None, buffer_object: None,
+            shm_pool: None, shm_buffer: None, buffer: None,
+            image_capture_source: None, image_copy_session: None, constraints: Constraints::default(), new_constraints: Constraints::default(), crop: None, frame_done: false,
+            buffer_transform: None, output_transform: Transform::Normal, y_invert: false, source: None, parts: Vec::new(), image_copy_frame: None,
+            buffer_size: (0, 0), damage: None, copied: false, linear: false,
+            fail: false, outdated: false, output,
+            x, y, width, height
+        }
+    }
+
+    ///
+    /// Create a capture session that is cut from a shared session capturing the entire output
+    ///
+    /// The session is not captured on its own, it does not need to be prepared.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The id of the shared session
+    /// * `output` - The output of the shared session
+    /// * `x` - The x position of the region
+    /// * `y` - The y position of the region
+    /// * `width` - The width of the region
+    /// * `height` - The height of the region
+    ///
+    pub fn cut_from(source: u64, output: WlOutput, x: i32, y: i32, width: i32, height: i32) -> Self {
+        let mut session = CaptureSession::new(output, x, y, width, height);
+        session.source = Some(source);
+        session
+    }
+
+    ///
+    /// Create a capture session that is composed of regions of shared sessions, e.g. to span multiple outputs
+    ///
+    /// The session is not captured on its own, it does not need to be prepared.
+    ///
+    /// # Arguments
+    ///
+    /// * `parts` - The ids of the shared sessions and their outputs, with the region of each as x, y, width and height relative to the size of the output
+    /// * `x` - The x position of the region in the global layout
+    /// * `y` - The y position of the region in the global layout
+    /// * `width` - The width of the region
+    /// * `height` - The height of the region
+    ///
+    /// # Panics
+    ///
+    /// This function panics if there are no parts.
+    ///
+    pub fn compose(parts: Vec<(u64, WlOutput, RelativeRect)>, x: i32, y: i32, width: i32, height: i32) -> Self {
+        let mut session = CaptureSession::new(parts[0].1.clone(), x, y, width, height);
+        session.parts = parts.into_iter().map(|(source, _, region)| (source, region)).collect();
+        session
+    }
+
+    ///
+    /// Get the ids of the shared sessions the session is cut from or composed of
+    ///
+    fn sources(&self) -> Vec<u64> {
+        self.source.into_iter().chain(self.parts.iter().map(|(source, _)| *source)).collect()
+    }
+
+    ///
+    /// Create an unprepared copy of the session that captures into a dmabuf with the linear modifier
+    ///
+    /// Linear buffers can be imported by every gpu, which helps if the buffer picked by the driver cannot be imported.
+    ///
+    pub fn linear(&self) -> Self {
+        let mut session = CaptureSession::new(self.output.clone(), self.x, self.y, self.width, self.height);
+        session.source = self.source;
+        session.linear = true;
+        session
+    }
+
+    ///
+    /// Check whether the session captures into a dmabuf with the linear modifier
+    ///
+    pub fn is_linear(&self) -> bool {
+        self.linear
+    }
+
+    ///
+    /// Get the buffer object the session captures into (only available once the session was prepared with dmabuf capture)
+    ///
+    pub fn buffer_object(&self) -> Option<&BufferObject<()>> {
+        self.buffer_object.as_ref()
+    }
+
+    ///
+    /// Get the shared memory buffer the session captures into (only available once the session was prepared with shm capture)
+    ///
+    pub fn shm_buffer(&self) -> Option<&ShmBuffer> {
+        self.shm_buffer.as_ref()
+    }
+
+    ///
+    /// Get the region of the upright buffer that holds the requested region as x, y, width and height relative to its size
+    ///
+    /// This is only set if the backend captures entire outputs, otherwise the entire buffer object is the requested region.
+    ///
+    pub fn crop(&self) -> Option<(f32, f32, f32, f32)> {
+        self.crop
+    }
+
+    ///
+    /// Get the transform that has to be undone to turn the buffer upright (only known once the session captured a frame)
+    ///
+    pub fn transform(&self) -> Transform {
+        let transform = self.buffer_transform.unwrap_or(self.output_transform);
+        if self.y_invert { flip_vertically(transform) } else { transform }
     }
 
 }
@@ -245,13 +1008,203 @@ impl Drop for CaptureSession {
         if let Some(ref buffer) = self.buffer {
             buffer.destroy();
         }
+        if let Some(ref shm_pool) = self.shm_pool {
+            shm_pool.destroy();
+        }
+        if let Some(ref image_copy_frame) = self.image_copy_frame {
+            image_copy_frame.destroy();
+        }
+        if let Some(ref image_copy_session) = self.image_copy_session {
+            image_copy_session.destroy();
+        }
+        if let Some(ref image_capture_source) = self.image_capture_source {
+            image_capture_source.destroy();
+        }
     }
 }
 
 impl Screencopy {
 
     ///
-    /// Capture the output of a session.
+    /// Request a new frame for every session at once, without waiting for them to be copied.
+    ///
+    /// Sessions cut from a shared session request a single frame of it. Sessions that failed are skipped, their
+    /// failure is reported by `frame_status` until they are recreated. Use `wait_for_frames` to wait for the frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `sessions` - The ids of the sessions
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the requests cannot be sent to the wayland server.
+    ///
+    pub fn request_frames(&mut self, sessions: impl IntoIterator<Item = u64>) -> Result<(), anyhow::Error> {
+        let qh = self.eq.as_ref().unwrap().handle();
+        let ids = sessions.into_iter().flat_map(|session| self.captured_sessions(session)).collect::<Vec<u64>>();
+        for id in ids {
+            let Some(capture_session) = self.sessions.get_mut(&id).filter(|capture_session| !capture_session.fail && !capture_session.is_pending()) else {
+                continue;
+            };
+            let Some(buffer) = capture_session.buffer.as_ref() else {
+                continue;
+            };
+
+            capture_session.frame_done = false;
+            capture_session.copied = false;
+            capture_session.damage = None;
+            capture_session.output_transform = self.outputs.get(&capture_session.output).map(|info| info.transform).unwrap_or(Transform::Normal);
+            match self.backend {
+                Backend::Wlr => {
+                    // the frame is copied as soon as the buffer parameters are known, see the dispatch
+                    if let Some(screencopy_frame) = capture_session.screencopy_frame.take() {
+                        screencopy_frame.destroy();
+                    }
+                    let screencopy_mgmt = self.wlr_screencopy_manager.as_ref().unwrap();
+                    capture_session.screencopy_frame = Some(screencopy_mgmt.capture_output_region(0, &capture_session.output,
+                        capture_session.x, capture_session.y, capture_session.width, capture_session.height, &qh, id));
+                },
+                Backend::Ext => {
+                    let (width, height) = capture_session.constraints.buffer_size.unwrap();
+                    let frame = capture_session.image_copy_session.as_ref().unwrap().create_frame(&qh, id);
+                    frame.attach_buffer(buffer);
+                    frame.damage_buffer(0, 0, width as i32, height as i32);
+                    frame.capture();
+                    capture_session.image_copy_frame = Some(frame);
+                }
+            }
+            trace!("requested frame for session {:#x}", id);
+        }
+
+        self.wl.flush().context("failed to send frame requests")?;
+        Ok(())
+    }
+
+    ///
+    /// Wait until every frame requested with `request_frames` is either copied or failed, or the timeout passed.
+    ///
+    /// Compositors only copy frames once their output is damaged, so frames of idle outputs stay pending and are
+    /// waited for again next time. Output events are dispatched along the way, see `poll_outputs`.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The longest time to wait
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the events cannot be dispatched.
+    ///
+    pub fn wait_for_frames(&mut self, timeout: Duration) -> Result<(), anyhow::Error> {
+        let deadline = Instant::now() + timeout;
+        let mut eq = self.eq.take().unwrap();
+        let status = self.dispatch_frames(&mut eq, deadline);
+        self.eq = Some(eq);
+        status.context("failed to dispatch frame events")?;
+
+        for capture_session in self.sessions.values_mut().filter(|capture_session| capture_session.frame_done) {
+            if let Some(image_copy_frame) = capture_session.image_copy_frame.take() {
+                image_copy_frame.destroy();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn dispatch_frames(&mut self, eq: &mut EventQueue<Screencopy>, deadline: Instant) -> Result<(), anyhow::Error> {
+        loop {
+            eq.dispatch_pending(self)?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || !self.sessions.values().any(CaptureSession::is_pending) {
+                return Ok(());
+            }
+
+            // wait for the socket to become readable until the deadline
+            eq.flush()?;
+            let Some(guard) = eq.prepare_read() else {
+                continue;
+            };
+            let mut pollfd = libc::pollfd { fd: guard.connection_fd().as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            if unsafe { libc::poll(&mut pollfd, 1, remaining.as_millis().max(1) as i32) } <= 0 {
+                continue; // timed out or interrupted, dropping the guard cancels the read
+            }
+            match guard.read() {
+                Ok(_) => (),
+                Err(WaylandError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => (),
+                Err(e) => return Err(e.into())
+            }
+        }
+    }
+
+    ///
+    /// Get the status of the last frame of a session.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The id of the session
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the session is not found or its last frame failed, in which case it has to be recreated.
+    ///
+    pub fn frame_status(&self, session: u64) -> Result<(), anyhow::Error> {
+        self.sessions.get(&session).context("session not found")?;
+        for id in self.captured_sessions(session) {
+            if self.sessions.get(&id).context("shared session not found")?.fail {
+                return Err(anyhow!("copy failed"));
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Check whether the region of a session changed in the frame copied by the last `wait_for_frames`.
+    ///
+    /// Sessions that were just prepared count as changed, so their first frame is rendered.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The id of the session
+    ///
+    pub fn is_damaged(&self, session: u64) -> bool {
+        let Some(capture_session) = self.sessions.get(&session) else {
+            return false;
+        };
+
+        // the region in the upright output captured by the source, relative to its size
+        let parts = match (capture_session.source, self.backend) {
+            _ if !capture_session.parts.is_empty() => capture_session.parts.clone(),
+            (Some(source), _) => vec![(source, self.crop(capture_session))],
+            (None, Backend::Ext) => vec![(session, capture_session.crop.unwrap_or((0.0, 0.0, 1.0, 1.0)))],
+            (None, Backend::Wlr) => vec![(session, (0.0, 0.0, 1.0, 1.0))]
+        };
+        parts.into_iter().any(|(source, region)| self.sessions.get(&source).is_some_and(|source| source.is_damaged_in(region)))
+    }
+
+    ///
+    /// Get the shared sessions a session is composed of, with the region of each as x, y, width and height relative to the size of its output
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The id of the session
+    ///
+    pub fn parts(&self, session: u64) -> Option<&[(u64, RelativeRect)]> {
+        self.sessions.get(&session).map(|capture_session| capture_session.parts.as_slice())
+    }
+
+    ///
+    /// Get the sessions capturing into shared memory whose last frame was copied, along with their buffer.
+    ///
+    /// This includes shared sessions, but not the sessions cut from them.
+    ///
+    pub fn shm_frames(&self) -> impl Iterator<Item = (u64, &ShmBuffer)> {
+        self.sessions.iter()
+            .filter(|(_, capture_session)| capture_session.copied)
+            .filter_map(|(id, capture_session)| capture_session.shm_buffer().map(|shm| (*id, shm)))
+    }
+
+    ///
+    /// Capture the output of a session that is not part of the screencopy, waiting for every step and allocating its buffer.
     ///
     /// # Arguments
     ///
@@ -259,30 +1212,39 @@ impl Screencopy {
     ///
     /// # Errors
     ///
-    /// This function will return an error if the session is not found, if the session is marked as failed or if the dmabuf capture is not supported.
+    /// This function will return an error if the session is marked as failed or if neither dmabuf nor shm capture is supported.
     /// It will also fail if any of the dispatches fail.
     ///
-    pub fn capture(&mut self, session: u64) -> Result<(), anyhow::Error> {
-        let session = self.sessions.get_mut(&session).context("session not found")?;
+    fn capture_session(&self, session: &mut CaptureSession) -> Result<(), anyhow::Error> {
         if session.fail {
             return Err(anyhow!("session is marked as failed"));
         }
 
+        session.output_transform = self.outputs.get(&session.output).map(|info| info.transform).unwrap_or(Transform::Normal);
+        match self.backend {
+            Backend::Wlr => self.capture_wlr(session),
+            Backend::Ext => self.capture_ext(session)
+        }
+    }
+
+    ///
+    /// Capture the region of a session using zwlr_screencopy_manager_v1.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The session to capture
+    ///
+    /// # Errors
+    ///
+    /// See `capture_session`.
+    ///
+    fn capture_wlr(&self, session: &mut CaptureSession) -> Result<(), anyhow::Error> {
         let mut eq = self.wl.new_event_queue::<CaptureSession>();
         let output = &session.output;
         let output_id = output.id().protocol_id();
-        let skip_dmabuf = session.buffer.is_some();
-
-        // reset the session if we are skipping dmabuf
-        if skip_dmabuf {
-            trace!("skipping dmabuf creation for capture");
-            session.screencopy_frame.as_mut().unwrap().destroy();
-            session.screencopy_frame = None;
-        }
 
         // get the required protocols
         let screencopy_mgmt = self.wlr_screencopy_manager.as_ref().unwrap();
-        let dmabuf_mgmt = self.wp_linux_dmabuf.as_ref().unwrap();
 
         // request output capture
         session.fail = true;
@@ -294,49 +1256,90 @@ impl Screencopy {
         }
 
         let screencopy_frame = session.screencopy_frame.as_ref().unwrap().clone();
-        let (fourcc, width, height) = session.requested_dmabuf_params.context("dmabuf capture not supported")?;
         trace!("created output capture with id {} for region {}x{}+{}+{} on {:?}", screencopy_frame.id().protocol_id(), session.width, session.height, session.x, session.y, output_id);
 
         // create buffer
-        let buffer =
-            if !skip_dmabuf {
-                let bo = self.gbm.create_buffer_object::<()>(width, height, gbm::Format::try_from(fourcc).unwrap(), BufferObjectFlags::RENDERING)
-                    .context("failed to create buffer object")?;
-                debug!("allocated dmabuf with format {} and size {}x{}", fourcc, width, height);
-
-                let linux_buffer_params = dmabuf_mgmt.create_params(&eq.handle(), ());
-                unsafe {
-                    let modifiers: u64 = bo.modifier().unwrap().into();
-                    linux_buffer_params.add(
-                        BorrowedFd::borrow_raw(bo.fd_for_plane(0).unwrap().as_raw_fd()),
-                        0,
-                        bo.offset(0).unwrap(),
-                        bo.stride_for_plane(0).unwrap(),
-                        (modifiers >> 32) as u32, modifiers as u32
-                    );
-                }
+        let dmabuf = session.requested_dmabuf_params.map(|(fourcc, width, height)| (fourcc, self.dmabuf_feedback.modifiers(fourcc), width, height));
+        let shm = session.requested_shm_params;
+        self.allocate_buffer(session, &mut eq, dmabuf, shm)?;
 
-                // request wl_buffer
-                session.fail = false;
-                linux_buffer_params.create(width as i32, height as i32, fourcc, Flags::empty());
-                eq.blocking_dispatch(session).context("create wl buffer roundtrip failed")?;
+        // copy the buffer
+        session.frame_done = false;
+        screencopy_frame.copy(session.buffer.as_ref().unwrap());
+        while !session.frame_done {
+            eq.blocking_dispatch(session).context("copy frame roundtrip failed")?;
+        }
 
-                if session.fail {
-                    return Err(anyhow!("failed to create wl buffer"));
-                }
+        if session.fail {
+            return Err(anyhow!("copy failed"));
+        }
 
-                debug!("created wl buffer {}", session.buffer.as_ref().unwrap().id().protocol_id());
-                session.linux_buffer_params = Some(linux_buffer_params);
-                session.buffer_object = Some(bo);
-                session.buffer.as_mut().unwrap()
-            } else {
-                session.buffer.as_mut().unwrap()
-            };
+        trace!("frame copied to buffer {}", session.buffer.as_ref().unwrap().id().protocol_id());
+        Ok(())
+    }
 
-        // copy the buffer
-        session.fail = true;
-        screencopy_frame.copy(buffer);
-        eq.blocking_dispatch(session).context("copy frame roundtrip failed")?; // this will wait for the dispatches to finish
+    ///
+    /// Capture the output of a session using ext_image_copy_capture_manager_v1.
+    ///
+    /// The entire output is captured, the requested region is exposed through `CaptureSession::crop` instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The session to capture
+    ///
+    /// # Errors
+    ///
+    /// See `capture_session`.
+    ///
+    fn capture_ext(&self, session: &mut CaptureSession) -> Result<(), anyhow::Error> {
+        let mut eq = self.wl.new_event_queue::<CaptureSession>();
+        let output_id = session.output.id().protocol_id();
+
+        // create the capture session and its buffer, later frames are requested with `request_frames`
+        let source_mgmt = self.ext_output_source_manager.as_ref().unwrap();
+        let copy_capture_mgmt = self.ext_image_copy_capture_manager.as_ref().unwrap();
+
+        let source = source_mgmt.create_source(&session.output, &eq.handle(), ());
+        session.image_copy_session = Some(copy_capture_mgmt.create_session(&source, Options::empty(), &eq.handle(), ()));
+        session.image_capture_source = Some(source);
+
+        // wait for the buffer constraints
+        while !session.constraints.done && !session.fail {
+            eq.blocking_dispatch(session).context("buffer constraints roundtrip failed")?;
+        }
+
+        if session.fail {
+            return Err(anyhow!("capture session was stopped"));
+        }
+
+        let (width, height) = session.constraints.buffer_size.context("no buffer size advertised")?;
+        let dmabuf = PREFERRED_FORMATS.iter()
+            .find_map(|format| session.constraints.dmabuf_formats.iter().find(|(fourcc, _)| *fourcc == *format as u32))
+            .map(|(fourcc, modifiers)| (*fourcc, modifiers.clone(), width, height));
+        let shm = PREFERRED_FORMATS.iter()
+            .find_map(|format| session.constraints.shm_formats.iter().find(|shm_format| shm_format_to_fourcc(**shm_format) == *format as u32))
+            .map(|format| (*format, width, height, width * 4));
+        trace!("created image copy capture session with id {} on {:?}", session.image_copy_session.as_ref().unwrap().id().protocol_id(), output_id);
+
+        self.allocate_buffer(session, &mut eq, dmabuf, shm)?;
+        session.crop = Some(self.crop(session));
+
+        // capture a frame into the buffer
+        let (width, height) = session.constraints.buffer_size.unwrap();
+        let frame = session.image_copy_session.as_ref().unwrap().create_frame(&eq.handle(), ());
+        frame.attach_buffer(session.buffer.as_ref().unwrap());
+        frame.damage_buffer(0, 0, width as i32, height as i32);
+
+        session.fail = false;
+        session.frame_done = false;
+        frame.capture();
+        while !session.frame_done {
+            if let Err(e) = eq.blocking_dispatch(session) {
+                frame.destroy();
+                return Err(e).context("capture frame roundtrip failed");
+            }
+        }
+        frame.destroy();
 
         if session.fail {
             return Err(anyhow!("copy failed"));
@@ -346,6 +1349,168 @@ impl Screencopy {
         Ok(())
     }
 
+    ///
+    /// Allocate the buffer of a session, preferring dmabuf over shared memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The session to allocate the buffer for
+    /// * `eq` - The event queue of the capture
+    /// * `dmabuf` - The requested dmabuf fourcc, modifiers, width and height, if dmabuf capture is offered
+    /// * `shm` - The requested wl_shm format, width, height and stride, if shm capture is offered
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if neither buffer type is supported or the buffer cannot be created.
+    ///
+    fn allocate_buffer(&self, session: &mut CaptureSession, eq: &mut EventQueue<CaptureSession>, dmabuf: Option<(u32, Vec<u64>, u32, u32)>, shm: Option<(u32, u32, u32, u32)>) -> Result<(), anyhow::Error> {
+        if let Some((fourcc, modifiers, width, height)) = dmabuf.filter(|_| self.gbm.is_some() && self.wp_linux_dmabuf.is_some()) {
+            match self.create_buffer(session, eq, fourcc, width, height, &modifiers) {
+                Ok(()) => return Ok(()),
+                Err(e) if shm.is_some() => warn!("failed to create dmabuf, falling back to shared memory: {:?}", e),
+                Err(e) => return Err(e)
+            }
+        }
+
+        let (format, width, height, stride) = shm.context("neither dmabuf nor shm capture supported")?;
+        self.create_shm_buffer(session, eq, format, width, height, stride)
+    }
+
+    ///
+    /// Allocate the dmabuf of a session and wrap it in a wl_buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The session to allocate the buffer for
+    /// * `eq` - The event queue of the capture
+    /// * `fourcc` - The drm format of the buffer
+    /// * `width` - The width of the buffer
+    /// * `height` - The height of the buffer
+    /// * `modifiers` - The allowed modifiers, an empty list lets the driver pick an implicit one
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the buffer object or the wl_buffer cannot be created.
+    ///
+    fn create_buffer(&self, session: &mut CaptureSession, eq: &mut EventQueue<CaptureSession>, fourcc: u32, width: u32, height: u32, modifiers: &[u64]) -> Result<(), anyhow::Error> {
+        let gbm = self.gbm.as_ref().unwrap();
+        let dmabuf_mgmt = self.wp_linux_dmabuf.as_ref().unwrap();
+        let format = gbm::Format::try_from(fourcc).context("unsupported dmabuf format")?;
+
+        // allocate the buffer object, using an explicit modifier if any was requested
+        let mut explicit_modifiers = modifiers.iter().copied().filter(|modifier| *modifier != DRM_FORMAT_MOD_INVALID).collect::<Vec<u64>>();
+        let mut flags = BufferObjectFlags::RENDERING;
+        if session.linear {
+            // implicit modifiers are forced to linear by the flag instead
+            if !explicit_modifiers.is_empty() && !explicit_modifiers.contains(&DRM_FORMAT_MOD_LINEAR) {
+                return Err(anyhow!("linear modifier not supported by the compositor"));
+            }
+            explicit_modifiers.retain(|modifier| *modifier == DRM_FORMAT_MOD_LINEAR);
+            flags |= BufferObjectFlags::LINEAR;
+        }
+        let bo =
+            if explicit_modifiers.is_empty() {
+                gbm.create_buffer_object::<()>(width, height, format, flags)
+            } else {
+                gbm.create_buffer_object_with_modifiers2::<()>(width, height, format, explicit_modifiers.into_iter().map(gbm::Modifier::from), BufferObjectFlags::RENDERING)
+            }.context("failed to create buffer object")?;
+        let planes = bo.plane_count().context("failed to query planes of buffer object")?;
+        debug!("allocated dmabuf with format {}, modifier {:?}, {} plane(s) and size {}x{}", fourcc, bo.modifier(), planes, width, height);
+
+        // add every plane of the buffer object, compressed buffers carry their metadata in additional planes
+        let linux_buffer_params = dmabuf_mgmt.create_params(&eq.handle(), ());
+        let modifier: u64 = bo.modifier().context("failed to query modifier of buffer object")?.into();
+        for plane in 0..planes as i32 {
+            let fd = bo.fd_for_plane(plane).context("failed to export plane of buffer object")?;
+            linux_buffer_params.add(
+                fd.as_fd(),
+                plane as u32,
+                bo.offset(plane).context("failed to query offset of plane")?,
+                bo.stride_for_plane(plane).context("failed to query stride of plane")?,
+                (modifier >> 32) as u32, modifier as u32
+            );
+        }
+
+        // request wl_buffer
+        session.fail = false;
+        linux_buffer_params.create(width as i32, height as i32, fourcc, Flags::empty());
+        eq.blocking_dispatch(session).context("create wl buffer roundtrip failed")?;
+
+        if session.fail {
+            return Err(anyhow!("failed to create wl buffer"));
+        }
+
+        debug!("created wl buffer {}", session.buffer.as_ref().unwrap().id().protocol_id());
+        session.linux_buffer_params = Some(linux_buffer_params);
+        session.buffer_object = Some(bo);
+        session.buffer_size = (width, height);
+        Ok(())
+    }
+
+    ///
+    /// Allocate a shared memory buffer for a session and wrap it in a wl_buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The session to allocate the buffer for
+    /// * `eq` - The event queue of the capture
+    /// * `format` - The wl_shm format of the buffer
+    /// * `width` - The width of the buffer
+    /// * `height` - The height of the buffer
+    /// * `stride` - The bytes per row of the buffer
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the format is not supported or the buffer cannot be created.
+    ///
+    fn create_shm_buffer(&self, session: &mut CaptureSession, eq: &mut EventQueue<CaptureSession>, format: u32, width: u32, height: u32, stride: u32) -> Result<(), anyhow::Error> {
+        let shm_mgmt = self.wl_shm.as_ref().context("shm capture not supported")?;
+        let fourcc = shm_format_to_fourcc(format);
+        if !PREFERRED_FORMATS.iter().any(|preferred| *preferred as u32 == fourcc) {
+            return Err(anyhow!("unsupported shm format {}", format));
+        }
+
+        let shm_buffer = ShmBuffer::new(width, height, stride, fourcc).context("failed to create shm buffer")?;
+        debug!("allocated shm buffer with format {} and size {}x{}", fourcc, width, height);
+
+        let shm_pool = shm_mgmt.create_pool(shm_buffer.fd(), shm_buffer.size() as i32, &eq.handle(), ());
+        let buffer = shm_pool.create_buffer(0, width as i32, height as i32, stride as i32, wl_shm::Format::try_from(format).unwrap(), &eq.handle(), ());
+        debug!("created wl buffer {}", buffer.id().protocol_id());
+
+        session.shm_pool = Some(shm_pool);
+        session.shm_buffer = Some(shm_buffer);
+        session.buffer = Some(buffer);
+        session.buffer_size = (width, height);
+        Ok(())
+    }
+
+    ///
+    /// Convert the region of a session to pixels of a buffer holding its entire output.
+    ///
+    /// The region is scaled by the ratio between the buffer size and the logical size of the output.
+    /// If the logical size is unknown, the region is assumed to already be in pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The session to convert the region of
+    /// * `width` - The width of the buffer
+    /// * `height` - The height of the buffer
+    ///
+    fn crop(&self, session: &CaptureSession) -> (f32, f32, f32, f32) {
+        let Some((logical_width, logical_height)) = self.outputs.get(&session.output).and_then(|info| info.logical_size) else {
+            warn!("logical size of output unknown, capturing the entire output");
+            return (0.0, 0.0, 1.0, 1.0);
+        };
+
+        let x = (session.x as f32 / logical_width.max(1) as f32).clamp(0.0, 1.0);
+        let y = (session.y as f32 / logical_height.max(1) as f32).clamp(0.0, 1.0);
+        let width = (session.width as f32 / logical_width.max(1) as f32).clamp(0.0, 1.0 - x);
+        let height = (session.height as f32 / logical_height.max(1) as f32).clamp(0.0, 1.0 - y);
+        trace!("cropping region {}x{}+{}+{} to {}x{}+{}+{} of {}x{} output", session.width, session.height, session.x, session.y, width, height, x, y, logical_width, logical_height);
+
+        (x, y, width, height)
+    }
+
 }
 
 ///
@@ -358,21 +1523,193 @@ impl Dispatch<ZwlrScreencopyFrameV1, ()> for CaptureSession {
             session.screencopy_frame = Some(proxy.clone());
         }
 
-        // handle events
+        session.handle_wlr_frame_event(event);
+    }
+}
+
+///
+/// ZwlrScreencopyFrameV1 dispatch for frames requested with `request_frames`
+///
+impl Dispatch<ZwlrScreencopyFrameV1, u64> for Screencopy {
+    fn event(state: &mut Self, proxy: &ZwlrScreencopyFrameV1, event: <ZwlrScreencopyFrameV1 as Proxy>::Event, id: &u64, _: &Connection, _: &QueueHandle<Self>) {
+        // ignore frames of sessions that were replaced in the meantime
+        let Some(session) = state.sessions.get_mut(id).filter(|session| session.screencopy_frame.as_ref() == Some(proxy)) else {
+            return;
+        };
+
+        // copy into the existing buffer once all buffer parameters are known (buffer_done was added in version 3)
+        let copy = match event {
+            zwlr_screencopy_frame_v1::Event::BufferDone => true,
+            zwlr_screencopy_frame_v1::Event::Buffer { .. } => proxy.version() < 3,
+            _ => false
+        };
+        session.handle_wlr_frame_event(event);
+        if copy && proxy.version() >= 2 {
+            proxy.copy_with_damage(session.buffer.as_ref().unwrap()); // waits until the region is damaged
+        } else if copy {
+            proxy.copy(session.buffer.as_ref().unwrap());
+        }
+    }
+}
+
+impl CaptureSession {
+
+    ///
+    /// Handle an event of the zwlr_screencopy_frame_v1 of the session
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event
+    ///
+    fn handle_wlr_frame_event(&mut self, event: zwlr_screencopy_frame_v1::Event) {
+        let session = self;
         match event {
             zwlr_screencopy_frame_v1::Event::LinuxDmabuf { format, width, height } => {
                 session.fail = false;
                 session.requested_dmabuf_params = Some((format, width, height));
             },
+            zwlr_screencopy_frame_v1::Event::Buffer { format, width, height, stride } => {
+                session.fail = false;
+                session.requested_shm_params = Some((format.into(), width, height, stride));
+            },
+            zwlr_screencopy_frame_v1::Event::Flags { flags } => {
+                session.y_invert = flags.into_result().is_ok_and(|flags| flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert));
+            },
+            zwlr_screencopy_frame_v1::Event::Damage { x, y, width, height } => {
+                session.damage.get_or_insert_with(Vec::new).push((x as i32, y as i32, width as i32, height as i32));
+            },
             zwlr_screencopy_frame_v1::Event::Failed => {
                 session.fail = true;
+                session.frame_done = true;
             },
             zwlr_screencopy_frame_v1::Event::Ready { .. } => {
                 session.fail = false;
+                session.frame_done = true;
+                session.copied = true;
+            },
+            _ => {}
+        }
+    }
+
+    ///
+    /// Handle an event of the ext_image_copy_capture_frame_v1 of the session
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event
+    ///
+    fn handle_ext_frame_event(&mut self, event: ext_image_copy_capture_frame_v1::Event) {
+        let session = self;
+        match event {
+            ext_image_copy_capture_frame_v1::Event::Transform { transform } => {
+                session.buffer_transform = transform.into_result().ok();
+            },
+            ext_image_copy_capture_frame_v1::Event::Damage { x, y, width, height } => {
+                session.damage.get_or_insert_with(Vec::new).push((x, y, width, height));
+            },
+            ext_image_copy_capture_frame_v1::Event::Ready => {
+                session.frame_done = true;
+                session.copied = true;
+            },
+            ext_image_copy_capture_frame_v1::Event::Failed { reason } => {
+                trace!("frame capture failed: {:?}", reason);
+                session.fail = true; // also covers stopped sessions and changed buffer constraints, which require a new session
+                session.frame_done = true;
             },
             _ => {}
         }
     }
+
+    ///
+    /// Check whether a frame of the session was requested but is neither copied nor failed yet
+    ///
+    fn is_pending(&self) -> bool {
+        self.source.is_none() && self.parts.is_empty() && !self.frame_done
+    }
+
+    ///
+    /// Check whether the last copied frame damaged a region of the upright buffer
+    ///
+    /// # Arguments
+    ///
+    /// * `region` - The region as x, y, width and height relative to the size of the upright buffer
+    ///
+    fn is_damaged_in(&self, region: RelativeRect) -> bool {
+        if !self.copied {
+            return false;
+        }
+        let Some(damage) = &self.damage else {
+            return true;
+        };
+
+        damage.iter()
+            .map(|rect| upright_rect(self.transform(), *rect, self.buffer_size))
+            .any(|(x, y, width, height)| x < region.0 + region.2 && region.0 < x + width && y < region.1 + region.3 && region.1 < y + height)
+    }
+
+}
+
+///
+/// ExtImageCopyCaptureSessionV1 dispatch
+///
+impl Dispatch<ExtImageCopyCaptureSessionV1, ()> for CaptureSession {
+    fn event(session: &mut Self, _: &ExtImageCopyCaptureSessionV1, event: <ExtImageCopyCaptureSessionV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            ext_image_copy_capture_session_v1::Event::Stopped => session.fail = true,
+            event => session.constraints.handle_event(event)
+        }
+    }
+}
+
+///
+/// ExtImageCopyCaptureSessionV1 dispatch for the persistent sessions created with `set_capture_session`
+///
+impl Dispatch<ExtImageCopyCaptureSessionV1, u64> for Screencopy {
+    fn event(state: &mut Self, proxy: &ExtImageCopyCaptureSessionV1, event: <ExtImageCopyCaptureSessionV1 as Proxy>::Event, id: &u64, _: &Connection, _: &QueueHandle<Self>) {
+        // ignore sessions that were replaced in the meantime
+        let Some(session) = state.sessions.get_mut(id).filter(|session| session.image_copy_session.as_ref() == Some(proxy)) else {
+            return;
+        };
+
+        match event {
+            ext_image_copy_capture_session_v1::Event::Stopped => {
+                debug!("capture session {:#x} was stopped by the compositor", id);
+                session.fail = true;
+            },
+            ext_image_copy_capture_session_v1::Event::Done => {
+                // the first constraints repeat the ones the buffer was allocated for, later ones replace them
+                let constraints = std::mem::take(&mut session.new_constraints);
+                if constraints.buffer_size != session.constraints.buffer_size
+                    || constraints.dmabuf_formats != session.constraints.dmabuf_formats
+                    || constraints.shm_formats != session.constraints.shm_formats {
+                    info!("buffer constraints of capture session {:#x} changed, recreating it", id);
+                    session.outdated = true;
+                }
+            },
+            event => session.new_constraints.handle_event(event)
+        }
+    }
+}
+
+///
+/// ExtImageCopyCaptureFrameV1 dispatch
+///
+impl Dispatch<ExtImageCopyCaptureFrameV1, ()> for CaptureSession {
+    fn event(session: &mut Self, _: &ExtImageCopyCaptureFrameV1, event: <ExtImageCopyCaptureFrameV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        session.handle_ext_frame_event(event);
+    }
+}
+
+///
+/// ExtImageCopyCaptureFrameV1 dispatch for frames requested with `request_frames`
+///
+impl Dispatch<ExtImageCopyCaptureFrameV1, u64> for Screencopy {
+    fn event(state: &mut Self, proxy: &ExtImageCopyCaptureFrameV1, event: <ExtImageCopyCaptureFrameV1 as Proxy>::Event, id: &u64, _: &Connection, _: &QueueHandle<Self>) {
+        // ignore frames of sessions that were replaced in the meantime
+        if let Some(session) = state.sessions.get_mut(id).filter(|session| session.image_copy_frame.as_ref() == Some(proxy)) {
+            session.handle_ext_frame_event(event);
+        }
+    }
 }
 
 ///
@@ -408,6 +1745,20 @@ impl Dispatch<WlBuffer, ()> for CaptureSession {
     }
 }
 
+/// WlShmPool dispatch
+/// (there are no events)
+impl Dispatch<WlShmPool, ()> for CaptureSession {
+    fn event(_: &mut Self, _: &WlShmPool, _: <WlShmPool as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+    }
+}
+
+/// WlShm dispatch
+/// (supported formats are advertised per frame instead)
+impl Dispatch<WlShm, ()> for Screencopy {
+    fn event(_: &mut Self, _: &WlShm, _: <WlShm as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+    }
+}
+
 /// ZwpLinuxDmabufV1 dispatch
 /// (all events are deprecated)
 impl Dispatch<ZwpLinuxDmabufV1, ()> for Screencopy {
@@ -415,9 +1766,121 @@ impl Dispatch<ZwpLinuxDmabufV1, ()> for Screencopy {
     }
 }
 
+/// ExtImageCaptureSourceV1 dispatch
+/// (there are no events)
+impl Dispatch<ExtImageCaptureSourceV1, ()> for CaptureSession {
+    fn event(_: &mut Self, _: &ExtImageCaptureSourceV1, _: <ExtImageCaptureSourceV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+    }
+}
+
+/// ExtOutputImageCaptureSourceManagerV1 dispatch
+/// (there are no events)
+impl Dispatch<ExtOutputImageCaptureSourceManagerV1, ()> for Screencopy {
+    fn event(_: &mut Self, _: &ExtOutputImageCaptureSourceManagerV1, _: <ExtOutputImageCaptureSourceManagerV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+    }
+}
+
+/// ExtImageCopyCaptureManagerV1 dispatch
+/// (there are no events)
+impl Dispatch<ExtImageCopyCaptureManagerV1, ()> for Screencopy {
+    fn event(_: &mut Self, _: &ExtImageCopyCaptureManagerV1, _: <ExtImageCopyCaptureManagerV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+    }
+}
+
+/// ZxdgOutputManagerV1 dispatch
+/// (there are no events)
+impl Dispatch<ZxdgOutputManagerV1, ()> for Screencopy {
+    fn event(_: &mut Self, _: &ZxdgOutputManagerV1, _: <ZxdgOutputManagerV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+    }
+}
+
 /// ZwlrScreencopyManagerV1 dispatch
 /// (there are no events)
 impl Dispatch<ZwlrScreencopyManagerV1, ()> for Screencopy {
     fn event(_: &mut Self, _: &ZwlrScreencopyManagerV1, _: <ZwlrScreencopyManagerV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
     }
 }
+
+/// WlSeat dispatch
+/// (the seat is only used for idle notifications)
+impl Dispatch<WlSeat, ()> for Screencopy {
+    fn event(_: &mut Self, _: &WlSeat, _: <WlSeat as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+    }
+}
+
+/// ExtIdleNotifierV1 dispatch
+/// (there are no events)
+impl Dispatch<ExtIdleNotifierV1, ()> for Screencopy {
+    fn event(_: &mut Self, _: &ExtIdleNotifierV1, _: <ExtIdleNotifierV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+    }
+}
+
+///
+/// ExtIdleNotificationV1 dispatch
+///
+impl Dispatch<ExtIdleNotificationV1, ()> for Screencopy {
+    fn event(state: &mut Self, proxy: &ExtIdleNotificationV1, event: <ExtIdleNotificationV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        // ignore notifications that were replaced in the meantime
+        if state.idle_notification.as_ref() != Some(proxy) {
+            return;
+        }
+
+        match event {
+            ext_idle_notification_v1::Event::Idled => {
+                info!("user is idle");
+                state.idle = true;
+            },
+            ext_idle_notification_v1::Event::Resumed => {
+                info!("user is active again");
+                state.idle = false;
+            },
+            _ => {}
+        }
+    }
+}
+
+/// ZwlrOutputPowerManagerV1 dispatch
+/// (there are no events)
+impl Dispatch<ZwlrOutputPowerManagerV1, ()> for Screencopy {
+    fn event(_: &mut Self, _: &ZwlrOutputPowerManagerV1, _: <ZwlrOutputPowerManagerV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+    }
+}
+
+///
+/// ZwlrOutputPowerV1 dispatch
+///
+impl Dispatch<ZwlrOutputPowerV1, WlOutput> for Screencopy {
+    fn event(state: &mut Self, proxy: &ZwlrOutputPowerV1, event: <ZwlrOutputPowerV1 as Proxy>::Event, output: &WlOutput, _: &Connection, _: &QueueHandle<Self>) {
+        let Some(info) = state.outputs.get_mut(output) else {
+            return;
+        };
+
+        match event {
+            zwlr_output_power_v1::Event::Mode { mode } => {
+                let powered = mode.into_result().map_or(true, |mode| mode == zwlr_output_power_v1::Mode::On);
+                if powered != info.powered {
+                    info!("output {:?} powered {}", info.name, if powered { "on" } else { "off" });
+                    info.powered = powered;
+                }
+            },
+            zwlr_output_power_v1::Event::Failed => {
+                // the power mode is no longer reported, e.g. because another client controls it
+                warn!("lost power mode of output {:?}", info.name);
+                proxy.destroy();
+                info.output_power = None;
+                info.power_lost = true;
+                info.powered = true;
+            },
+            _ => {}
+        }
+    }
+}
+
+///
+/// ZwpLinuxDmabufFeedbackV1 dispatch
+///
+impl Dispatch<ZwpLinuxDmabufFeedbackV1, ()> for Screencopy {
+    fn event(state: &mut Self, _: &ZwpLinuxDmabufFeedbackV1, event: <ZwpLinuxDmabufFeedbackV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        state.dmabuf_feedback.handle_event(event);
+    }
+}
@@ -0,0 +1,153 @@
+use std::{collections::HashMap, fs, os::fd::{AsRawFd, OwnedFd}, path::PathBuf};
+
+use log::{debug, trace, warn};
+use wayland_protocols::wp::linux_dmabuf::zv1::client::zwp_linux_dmabuf_feedback_v1;
+
+///
+/// Default dmabuf feedback of the compositor
+///
+/// The feedback names the device the compositor renders with and the formats and modifiers it can import from it.
+///
+#[derive(Default)]
+pub struct DmabufFeedback {
+    format_table: Vec<(u32, u64)>, // fourcc, modifier
+    main_device: Option<u64>, // dev_t of the device the compositor renders with
+    tranche_device: Option<u64>, // dev_t of the device targeted by the current tranche
+    tranche_formats: Vec<u16>, // indices into the format table of the current tranche
+    formats: HashMap<u32, Vec<u64>>, // fourcc, modifiers importable from the main device
+    pub done: bool
+}
+
+impl DmabufFeedback {
+
+    ///
+    /// Handle an event of the feedback object
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to handle
+    ///
+    pub fn handle_event(&mut self, event: zwp_linux_dmabuf_feedback_v1::Event) {
+        match event {
+            zwp_linux_dmabuf_feedback_v1::Event::FormatTable { fd, size } => {
+                self.format_table = read_format_table(fd, size as usize);
+                trace!("received dmabuf format table with {} entries", self.format_table.len());
+            },
+            zwp_linux_dmabuf_feedback_v1::Event::MainDevice { device } => self.main_device = parse_dev_t(&device),
+            zwp_linux_dmabuf_feedback_v1::Event::TrancheTargetDevice { device } => self.tranche_device = parse_dev_t(&device),
+            zwp_linux_dmabuf_feedback_v1::Event::TrancheFormats { indices } => {
+                self.tranche_formats.extend(indices.chunks_exact(2).map(|index| u16::from_ne_bytes([index[0], index[1]])));
+            },
+            zwp_linux_dmabuf_feedback_v1::Event::TrancheDone => {
+                // resent feedback replaces the previous one, e.g. after the main device changed
+                if self.done {
+                    self.formats.clear();
+                    self.done = false;
+                }
+
+                // only tranches of the main device can be rendered with, others are meant for scanout
+                let indices = std::mem::take(&mut self.tranche_formats);
+                if self.tranche_device.take() == self.main_device {
+                    for (fourcc, modifier) in indices.into_iter().filter_map(|index| self.format_table.get(index as usize)) {
+                        self.formats.entry(*fourcc).or_default().push(*modifier);
+                    }
+                }
+            },
+            zwp_linux_dmabuf_feedback_v1::Event::Done => {
+                debug!("received dmabuf feedback: main device {:?} with {} formats", self.main_device, self.formats.len());
+                self.done = true;
+            },
+            _ => {}
+        }
+    }
+
+    ///
+    /// Get the modifiers the compositor can import for a format, empty if unknown
+    ///
+    /// # Arguments
+    ///
+    /// * `fourcc` - The drm format
+    ///
+    pub fn modifiers(&self, fourcc: u32) -> Vec<u64> {
+        self.formats.get(&fourcc).cloned().unwrap_or_default()
+    }
+
+    ///
+    /// Find the render node of the main device
+    ///
+    /// The main device may be a primary node like /dev/dri/card0, so the render node belonging to the same gpu is looked up in sysfs.
+    ///
+    pub fn render_node(&self) -> Option<PathBuf> {
+        let device = self.main_device?;
+        let (major, minor) = (libc::major(device), libc::minor(device));
+        let nodes = fs::read_dir(format!("/sys/dev/char/{}:{}/device/drm", major, minor)).ok()?;
+        nodes.filter_map(Result::ok)
+            .map(|node| node.file_name().to_string_lossy().into_owned())
+            .find(|node| node.starts_with("renderD"))
+            .map(|node| PathBuf::from("/dev/dri").join(node))
+    }
+
+}
+
+///
+/// Parse a dev_t sent as an array of bytes
+///
+fn parse_dev_t(device: &[u8]) -> Option<u64> {
+    Some(u64::from_ne_bytes(device.get(..8)?.try_into().ok()?))
+}
+
+///
+/// Read the format table shared by the compositor
+///
+/// Each entry consists of a 32 bit fourcc, 32 bits of padding and a 64 bit modifier.
+///
+/// # Arguments
+///
+/// * `fd` - The file descriptor of the table
+/// * `size` - The size of the table in bytes
+///
+fn read_format_table(fd: OwnedFd, size: usize) -> Vec<(u32, u64)> {
+    let data = unsafe { libc::mmap(std::ptr::null_mut(), size, libc::PROT_READ, libc::MAP_PRIVATE, fd.as_raw_fd(), 0) };
+    if data == libc::MAP_FAILED {
+        warn!("failed to map dmabuf format table: {:?}", std::io::Error::last_os_error());
+        return Vec::new();
+    }
+
+    let table = unsafe { std::slice::from_raw_parts(data as *const u8, size) };
+    let entries = table.chunks_exact(16)
+        .map(|entry| (u32::from_ne_bytes(entry[..4].try_into().unwrap()), u64::from_ne_bytes(entry[8..].try_into().unwrap())))
+        .collect();
+    unsafe { libc::munmap(data, size) };
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use wayland_protocols::wp::linux_dmabuf::zv1::client::zwp_linux_dmabuf_feedback_v1::Event;
+
+    use super::DmabufFeedback;
+
+    ///
+    /// Send feedback with a single tranche of the main device
+    ///
+    fn send(feedback: &mut DmabufFeedback, device: u64, indices: &[u16]) {
+        feedback.handle_event(Event::MainDevice { device: device.to_ne_bytes().to_vec() });
+        feedback.handle_event(Event::TrancheTargetDevice { device: device.to_ne_bytes().to_vec() });
+        feedback.handle_event(Event::TrancheFormats { indices: indices.iter().flat_map(|index| index.to_ne_bytes()).collect() });
+        feedback.handle_event(Event::TrancheDone);
+        feedback.handle_event(Event::Done);
+    }
+
+    #[test]
+    fn resent_feedback_replaces_formats() {
+        let mut feedback = DmabufFeedback { format_table: vec![(1, 10), (1, 11), (1, 20)], ..Default::default() };
+        send(&mut feedback, 1, &[0, 1]);
+        assert!(feedback.done);
+        assert_eq!(feedback.modifiers(1), [10, 11]);
+
+        send(&mut feedback, 2, &[2]);
+        assert!(feedback.done);
+        assert_eq!(feedback.modifiers(1), [20]);
+    }
+}
@@ -0,0 +1,86 @@
+use std::{ffi::CString, fs::File, os::fd::{AsFd, BorrowedFd, FromRawFd}};
+
+use anyhow::{anyhow, Context};
+use log::trace;
+
+///
+/// Memory mapped memfd backing a wl_shm buffer
+///
+pub struct ShmBuffer {
+    file: File,
+    data: *mut u8,
+    size: usize,
+
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32,
+    pub format: u32 // drm fourcc
+}
+
+impl ShmBuffer {
+
+    ///
+    /// Create a new ShmBuffer
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the buffer
+    /// * `height` - Height of the buffer
+    /// * `stride` - Bytes per row of the buffer
+    /// * `format` - The drm fourcc of the buffer
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the memfd cannot be created, resized or mapped
+    ///
+    pub fn new(width: u32, height: u32, stride: u32, format: u32) -> Result<Self, anyhow::Error> {
+        let size = stride as usize * height as usize;
+
+        // create the memfd
+        let name = CString::new("ambient-led-shm").unwrap();
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error()).context("failed to create memfd");
+        }
+        let file = unsafe { File::from_raw_fd(fd) };
+        file.set_len(size as u64).context("failed to resize memfd")?;
+
+        // map the memfd
+        let data = unsafe { libc::mmap(std::ptr::null_mut(), size, libc::PROT_READ, libc::MAP_SHARED, fd, 0) };
+        if data == libc::MAP_FAILED {
+            return Err(anyhow!(std::io::Error::last_os_error())).context("failed to map memfd");
+        }
+        trace!("created shm buffer: fd={}, size={}", fd, size);
+
+        Ok(Self { file, data: data as *mut u8, size, width, height, stride, format })
+    }
+
+    ///
+    /// Get the file descriptor of the buffer
+    ///
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+
+    ///
+    /// Get the size of the buffer in bytes
+    ///
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    ///
+    /// Get the pixels of the buffer
+    ///
+    pub fn pixels(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data, self.size) }
+    }
+
+}
+
+impl Drop for ShmBuffer {
+    fn drop(&mut self) {
+        trace!("dropping shm buffer: size={}", self.size);
+        unsafe { libc::munmap(self.data as *mut libc::c_void, self.size); }
+    }
+}